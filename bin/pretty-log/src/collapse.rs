@@ -0,0 +1,342 @@
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::message::{Message, Repeat, Severity};
+
+/// How long a run of repeated messages is held without further repeats before
+/// it is printed, when collapsing consecutive messages.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Parts of message texts that are masked before comparing them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mask {
+    Uuid,
+    Hex,
+    Number,
+}
+
+impl FromStr for Mask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mask, String> {
+        match s {
+            "uuid" => Ok(Mask::Uuid),
+            "hex" => Ok(Mask::Hex),
+            "number" => Ok(Mask::Number),
+            _ => Err(format!("Invalid mask: {}", s)),
+        }
+    }
+}
+
+/// Reduces message texts to what they have in common with similar messages,
+/// by masking numbers, IDs and matches of custom patterns.
+pub struct Normalizer {
+    masks: Vec<Mask>,
+    patterns: Vec<Regex>,
+}
+
+impl Normalizer {
+    pub fn new(masks: Vec<Mask>, patterns: Vec<Regex>) -> Normalizer {
+        Normalizer { masks, patterns }
+    }
+
+    /// Mask the text, where custom patterns are masked as `<*>`, UUIDs and
+    /// hexadecimal IDs (with at least one digit) as `<id>`, and numbers as `<n>`.
+    pub fn normalize(&self, text: &str) -> String {
+        lazy_static! {
+            static ref UUID: Regex =
+                Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b")
+                    .unwrap();
+            static ref HEX: Regex = Regex::new(r"(?i)\b(?:0x[0-9a-f]+|[0-9a-f]{8,})\b").unwrap();
+            static ref NUMBER: Regex = Regex::new(r"\b\d+(?:\.\d+)*").unwrap();
+        }
+
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            text = Cow::Owned(pattern.replace_all(&text, "<*>").into_owned());
+        }
+
+        // masks are applied in a fixed order, as numbers are part of IDs
+        if self.masks.contains(&Mask::Uuid) {
+            text = Cow::Owned(UUID.replace_all(&text, "<id>").into_owned());
+        }
+        if self.masks.contains(&Mask::Hex) {
+            text = Cow::Owned(
+                HEX.replace_all(&text, |captures: &Captures| {
+                    let hex = &captures[0];
+                    if hex.chars().any(|ch| ch.is_ascii_digit()) {
+                        "<id>".to_string()
+                    } else {
+                        hex.to_string()
+                    }
+                })
+                .into_owned(),
+            );
+        }
+        if self.masks.contains(&Mask::Number) {
+            text = Cow::Owned(NUMBER.replace_all(&text, "<n>").into_owned());
+        }
+
+        text.into_owned()
+    }
+}
+
+impl Default for Normalizer {
+    fn default() -> Normalizer {
+        Normalizer::new(vec![Mask::Uuid, Mask::Hex, Mask::Number], Vec::new())
+    }
+}
+
+/// Collapses repeated messages, with the same severity and the same
+/// normalized text, into the first of them with a repeat count.
+///
+/// Without a window, only consecutive repeats are collapsed. With a window,
+/// repeats within the window from the first message are collapsed even if
+/// other messages come in between, and messages are held back until their
+/// window has passed. Windows are measured in message time where messages
+/// have one, and otherwise in the time they were read.
+pub struct Collapser {
+    normalizer: Normalizer,
+    window: Option<Duration>,
+    runs: Mutex<VecDeque<Run>>,
+}
+
+struct Run {
+    key: (String, Severity),
+    message: Message,
+    count: usize,
+    last_time: Option<DateTime<Local>>,
+    first_seen: Instant,
+    last_seen: Instant,
+}
+
+impl Run {
+    fn into_message(self) -> Message {
+        let mut message = self.message;
+        if self.count > 1 {
+            message.repeat = Some(Repeat {
+                count: self.count,
+                last_time: self.last_time,
+            });
+        }
+        message
+    }
+}
+
+impl Collapser {
+    pub fn new(normalizer: Normalizer, window: Option<Duration>) -> Collapser {
+        Collapser {
+            normalizer,
+            window,
+            runs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Add a message, and emit the messages that can no longer be repeated.
+    pub fn push(
+        &self,
+        message: Message,
+        mut emit: impl FnMut(Message) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let now = Instant::now();
+        let key = (self.normalizer.normalize(&message.text), message.severity);
+        let mut runs = self.runs.lock().unwrap();
+
+        match self.window {
+            None => {
+                if runs.back().is_some_and(|run| run.key != key) {
+                    while let Some(run) = runs.pop_front() {
+                        emit(run.into_message())?;
+                    }
+                }
+            }
+            Some(window) => {
+                while runs
+                    .front()
+                    .is_some_and(|run| is_expired(run, message.time, now, window))
+                {
+                    emit(runs.pop_front().unwrap().into_message())?;
+                }
+            }
+        }
+
+        let run = runs.iter_mut().find(|run| {
+            run.key == key
+                && !self
+                    .window
+                    .is_some_and(|window| is_expired(run, message.time, now, window))
+        });
+        match run {
+            Some(run) => {
+                run.count += 1;
+                run.last_time = message.time.or(run.last_time);
+                run.last_seen = now;
+            }
+            None => runs.push_back(Run {
+                key,
+                last_time: message.time,
+                message,
+                count: 1,
+                first_seen: now,
+                last_seen: now,
+            }),
+        }
+
+        Ok(())
+    }
+
+    /// Emit runs that have been held for long enough without further input,
+    /// so that live input is not held back indefinitely.
+    pub fn flush_idle(&self, mut emit: impl FnMut(Message) -> io::Result<()>) -> io::Result<()> {
+        let now = Instant::now();
+        let mut runs = self.runs.lock().unwrap();
+
+        let is_idle = |run: &Run| match self.window {
+            Some(window) => now - run.first_seen >= window,
+            None => now - run.last_seen >= IDLE_TIMEOUT,
+        };
+        while runs.front().is_some_and(is_idle) {
+            emit(runs.pop_front().unwrap().into_message())?;
+        }
+
+        Ok(())
+    }
+
+    /// Emit all remaining runs, at the end of the input.
+    pub fn flush(&self, mut emit: impl FnMut(Message) -> io::Result<()>) -> io::Result<()> {
+        let mut runs = self.runs.lock().unwrap();
+        while let Some(run) = runs.pop_front() {
+            emit(run.into_message())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a message at `time` (read at `now`) is past the window of a run.
+fn is_expired(run: &Run, time: Option<DateTime<Local>>, now: Instant, window: Duration) -> bool {
+    match (run.message.time, time) {
+        (Some(first), Some(time)) => (time - first).to_std().is_ok_and(|delta| delta > window),
+        _ => now - run.first_seen > window,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn collapse(collapser: &Collapser, messages: Vec<Message>) -> Vec<Message> {
+        let mut output = Vec::new();
+        for message in messages {
+            collapser
+                .push(message, |message| {
+                    output.push(message);
+                    Ok(())
+                })
+                .unwrap();
+        }
+        collapser
+            .flush(|message| {
+                output.push(message);
+                Ok(())
+            })
+            .unwrap();
+        output
+    }
+
+    fn message(text: &str, seconds: u32) -> Message {
+        Message {
+            time: Some(Local.with_ymd_and_hms(2020, 3, 1, 12, 0, seconds).unwrap()),
+            ..Message::from_text(text, Severity::Info)
+        }
+    }
+
+    fn summary(messages: &[Message]) -> Vec<(&str, usize)> {
+        messages
+            .iter()
+            .map(|message| {
+                let count = message.repeat.as_ref().map_or(1, |repeat| repeat.count);
+                (message.text.as_str(), count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn custom_masks() {
+        let normalizer =
+            Normalizer::new(vec![Mask::Number], vec![Regex::new(r"user=\w+").unwrap()]);
+        assert_eq!(
+            normalizer.normalize("user=alice id=deadbeef12 took 3ms"),
+            "<*> id=deadbeef12 took <n>ms"
+        );
+        assert!("ip".parse::<Mask>().is_err());
+    }
+
+    #[test]
+    fn consecutive_repeats() {
+        let collapser = Collapser::new(Normalizer::default(), None);
+        let output = collapse(
+            &collapser,
+            vec![
+                message("retry 1", 0),
+                message("retry 2", 1),
+                message("retry 3", 2),
+                message("connected", 3),
+                message("retry 4", 4),
+                Message {
+                    severity: Severity::Error,
+                    ..message("retry 5", 5)
+                },
+            ],
+        );
+
+        assert_eq!(
+            summary(&output),
+            vec![
+                ("retry 1", 3),
+                ("connected", 1),
+                ("retry 4", 1),
+                ("retry 5", 1)
+            ]
+        );
+        assert_eq!(
+            output[0].repeat.as_ref().unwrap().last_time,
+            message("", 2).time
+        );
+        assert!(output[1].repeat.is_none());
+    }
+
+    #[test]
+    fn windowed_repeats() {
+        let collapser = Collapser::new(Normalizer::default(), Some(Duration::from_secs(10)));
+        let output = collapse(
+            &collapser,
+            vec![
+                message("poll 1", 0),
+                message("connected", 1),
+                message("poll 2", 5),
+                message("poll 3", 10),
+                message("poll 4", 11),
+                message("connected", 12),
+            ],
+        );
+
+        assert_eq!(
+            summary(&output),
+            vec![
+                ("poll 1", 3),
+                ("connected", 1),
+                ("poll 4", 1),
+                ("connected", 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::clap::ArgMatches;
+
+use crate::format::{FieldMapping, Theme};
+use crate::utils::parse_duration;
+use crate::{ColorChoice, ContextLayout, Options, StatsLayout};
+
+const CONFIG_FILE: &str = "config.toml";
+const PROJECT_CONFIG_FILE: &str = ".pretty-log.toml";
+
+/// Settings read from configuration files.
+///
+/// The top-level settings apply to every run, and each named profile can
+/// override them. Settings given on the command line take precedence.
+#[derive(Debug, Default)]
+pub struct Config {
+    defaults: Profile,
+    profiles: HashMap<String, Profile>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    input: Option<Vec<String>>,
+    output: Option<String>,
+    fields: Option<FieldMapping>,
+    multiline: Option<bool>,
+    continuation: Option<Vec<String>>,
+
+    level: Option<String>,
+    grep: Option<Vec<String>>,
+    #[serde(rename = "where")]
+    filter: Option<Vec<String>>,
+    since: Option<String>,
+    until: Option<String>,
+    collapse: Option<bool>,
+    collapse_window: Option<String>,
+    collapse_mask: Option<Vec<String>>,
+    collapse_pattern: Option<Vec<String>>,
+
+    sorted: Option<bool>,
+    merge: Option<bool>,
+    merge_window: Option<String>,
+
+    context: Option<bool>,
+    compact: Option<bool>,
+    expand_json: Option<bool>,
+    context_layout: Option<ContextLayout>,
+    context_order: Option<Vec<String>>,
+    include_context: Option<Vec<String>>,
+    exclude_context: Option<Vec<String>>,
+    context_width: Option<usize>,
+    color: Option<ColorChoice>,
+    width: Option<usize>,
+    template: Option<String>,
+    time_format: Option<String>,
+    utc: Option<bool>,
+    stats_keys: Option<Vec<String>>,
+    stats_top: Option<usize>,
+    stats_layout: Option<StatsLayout>,
+    theme: Option<Theme>,
+}
+
+// the top-level settings are not flattened with serde, since unknown keys
+// cannot be rejected together with `#[serde(flatten)]`
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Config, D::Error> {
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let profiles = match table.remove("profiles") {
+            Some(profiles) => profiles.try_into().map_err(D::Error::custom)?,
+            None => HashMap::new(),
+        };
+        let defaults = toml::Value::Table(table)
+            .try_into()
+            .map_err(D::Error::custom)?;
+
+        Ok(Config { defaults, profiles })
+    }
+}
+
+impl Config {
+    /// Load the configuration from `path`, or otherwise from the XDG config
+    /// directories and the nearest `.pretty-log.toml` in the current directory
+    /// or its parents, where later files override earlier ones.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let paths = match path {
+            Some(path) => vec![path.to_path_buf()],
+            None => config_paths()
+                .into_iter()
+                .filter(|path| path.is_file())
+                .collect(),
+        };
+
+        let mut config = Config::default();
+        for path in paths {
+            let data = fs::read_to_string(&path)
+                .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+            let file: Config = toml::from_str(&data)
+                .map_err(|err| format!("Invalid config file {}: {}", path.display(), err))?;
+            config.extend(file);
+        }
+
+        Ok(config)
+    }
+
+    fn extend(&mut self, other: Config) {
+        self.defaults.extend(other.defaults);
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().extend(profile);
+        }
+    }
+
+    /// Get the settings to use, with the given profile (if any) applied.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, String> {
+        let mut profile = self.defaults.clone();
+
+        if let Some(name) = name {
+            match self.profiles.get(name) {
+                Some(overrides) => profile.extend(overrides.clone()),
+                None => {
+                    let mut names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+                    names.sort_unstable();
+                    return Err(format!(
+                        "Unknown profile: {} (available profiles: {})",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            }
+        }
+
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    fn extend(&mut self, other: Profile) {
+        macro_rules! extend {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    if other.$field.is_some() {
+                        self.$field = other.$field;
+                    }
+                )*
+            };
+        }
+
+        extend!(
+            input,
+            output,
+            fields,
+            multiline,
+            continuation,
+            level,
+            grep,
+            filter,
+            since,
+            until,
+            collapse,
+            collapse_window,
+            collapse_mask,
+            collapse_pattern,
+            sorted,
+            merge,
+            merge_window,
+            context,
+            compact,
+            expand_json,
+            context_layout,
+            context_order,
+            include_context,
+            exclude_context,
+            context_width,
+            color,
+            width,
+            template,
+            time_format,
+            utc,
+            stats_keys,
+            stats_top,
+            stats_layout,
+            theme,
+        );
+    }
+
+    /// Apply the settings to options that were not given on the command line.
+    pub fn apply(self, opts: &mut Options, matches: &ArgMatches) -> Result<(), String> {
+        let is_unset = |name: &str| matches.occurrences_of(name) == 0;
+
+        macro_rules! apply {
+            ($($field:ident => $arg:literal : $target:expr),* $(,)?) => {
+                $(
+                    if let Some(value) = self.$field {
+                        if is_unset($arg) {
+                            $target = value.into();
+                        }
+                    }
+                )*
+            };
+        }
+
+        apply!(
+            input => "input-format": opts.input_format,
+            output => "output-format": opts.output_format,
+            multiline => "multiline": opts.input_options.multiline,
+            continuation => "continuation-patterns": opts.input_options.continuation_patterns,
+            grep => "patterns": opts.filter_options.patterns,
+            filter => "expressions": opts.filter_options.expressions,
+            sorted => "sorted": opts.sorted,
+            merge => "merge": opts.merge,
+            context => "show-context": opts.display_options.show_context,
+            compact => "compact": opts.display_options.compact,
+            expand_json => "expand-json": opts.display_options.expand_json,
+            context_layout => "context-layout": opts.display_options.context_layout,
+            context_order => "context-order": opts.display_options.context_order,
+            include_context => "include-context": opts.display_options.include_context,
+            exclude_context => "exclude-context": opts.display_options.exclude_context,
+            context_width => "context-width": opts.display_options.context_width,
+            color => "color": opts.display_options.color,
+            width => "width": opts.display_options.width,
+            template => "template": opts.display_options.template,
+            time_format => "time-format": opts.display_options.time_format,
+            utc => "utc": opts.display_options.utc,
+            stats_keys => "stats-keys": opts.display_options.stats_keys,
+            stats_top => "stats-top": opts.display_options.stats_top,
+            stats_layout => "stats-layout": opts.display_options.stats_layout,
+            level => "min-severity": opts.filter_options.min_severity,
+            since => "since": opts.filter_options.since,
+            until => "until": opts.filter_options.until,
+            collapse => "collapse": opts.filter_options.collapse,
+            collapse_mask => "collapse-masks": opts.filter_options.collapse_masks,
+            collapse_pattern => "collapse-patterns": opts.filter_options.collapse_patterns,
+        );
+
+        if let Some(window) = self.merge_window {
+            if is_unset("merge-window") {
+                opts.merge_window = parse_duration(&window)?;
+            }
+        }
+
+        if let Some(window) = self.collapse_window {
+            if is_unset("collapse-window") {
+                opts.filter_options.collapse_window = Some(parse_duration(&window)?);
+            }
+        }
+
+        opts.input_options.config_fields = self.fields;
+        if let Some(theme) = self.theme {
+            opts.theme = theme;
+        }
+
+        Ok(())
+    }
+}
+
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    let config_dirs = env::var("XDG_CONFIG_DIRS").unwrap_or_else(|_| "/etc/xdg".to_string());
+    for dir in config_dirs.rsplit(':').filter(|dir| !dir.is_empty()) {
+        paths.push(Path::new(dir).join("pretty-log").join(CONFIG_FILE));
+    }
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("pretty-log").join(CONFIG_FILE));
+    }
+
+    if let Ok(dir) = env::current_dir() {
+        if let Some(dir) = dir
+            .ancestors()
+            .find(|dir| dir.join(PROJECT_CONFIG_FILE).is_file())
+        {
+            paths.push(dir.join(PROJECT_CONFIG_FILE));
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profiles() {
+        let mut config: Config = toml::from_str(
+            r#"
+            input = ["json"]
+            level = "info"
+
+            [profiles.k8s]
+            input = ["json", "logfmt"]
+            where = ["namespace=prod"]
+
+            [profiles.k8s.fields]
+            message = ["msg"]
+            "#,
+        )
+        .unwrap();
+
+        config.extend(
+            toml::from_str(
+                r#"
+                [profiles.k8s]
+                level = "warning"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        let profile = config.profile(None).unwrap();
+        assert_eq!(profile.input, Some(vec!["json".to_string()]));
+        assert_eq!(profile.level.as_deref(), Some("info"));
+
+        let profile = config.profile(Some("k8s")).unwrap();
+        assert_eq!(profile.input.unwrap().len(), 2);
+        assert_eq!(profile.level.as_deref(), Some("warning"));
+        assert_eq!(profile.filter, Some(vec!["namespace=prod".to_string()]));
+        assert_eq!(profile.fields.unwrap().message, vec!["msg".to_string()]);
+
+        assert!(config.profile(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let error = toml::from_str::<Config>("colour = \"never\"").unwrap_err();
+        assert!(error.to_string().contains("unknown field `colour`"));
+
+        let error = toml::from_str::<Config>("[profiles.k8s]\nlevl = \"info\"").unwrap_err();
+        assert!(error.to_string().contains("unknown field `levl`"));
+    }
+}
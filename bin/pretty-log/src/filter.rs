@@ -0,0 +1,293 @@
+use regex::Regex;
+use std::cmp::Ordering;
+
+use crate::message::{Message, Severity, Value};
+use crate::parse::{self, parse, regex_token, ParseBuffer, ParseError, QuotedString};
+
+/// A predicate deciding which messages are printed.
+///
+/// Filter expressions combine predicates with `and`/`&&`, `or`/`||`,
+/// `not`/`!` and parentheses. A predicate is either a field name on its own,
+/// which checks that the field is present, or a comparison such as
+/// `status>=500`, `service=api` or `msg~"timed? out"`. The fields `level` and
+/// `msg` refer to the severity and text of the message, and any other field to
+/// a (possibly dotted) key in the message context.
+#[derive(Debug)]
+pub enum Filter {
+    All(Vec<Filter>),
+    Any(Vec<Filter>),
+    Not(Box<Filter>),
+    MinSeverity(Severity),
+    Text(Regex),
+    Field(FieldPredicate),
+}
+
+#[derive(Debug)]
+pub struct FieldPredicate {
+    field: Field,
+    op: Option<(Operator, Operand)>,
+}
+
+#[derive(Debug)]
+enum Field {
+    Severity,
+    Text,
+    Context(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug)]
+enum Operand {
+    Severity(Severity),
+    Regex(Regex),
+    Value(String),
+}
+
+impl Filter {
+    pub fn parse(expr: &str) -> Result<Filter, String> {
+        parse::<Filter>(expr.trim())
+            .map_err(|err| format!("Invalid filter expression {:?}: {}", expr, err))
+    }
+
+    pub fn matches(&self, message: &Message) -> bool {
+        match self {
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(message)),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(message)),
+            Filter::Not(filter) => !filter.matches(message),
+            Filter::MinSeverity(severity) => message.severity >= *severity,
+            Filter::Text(regex) => regex.is_match(&message.text),
+            Filter::Field(predicate) => predicate.matches(message),
+        }
+    }
+}
+
+impl FieldPredicate {
+    fn matches(&self, message: &Message) -> bool {
+        let value = match &self.field {
+            Field::Severity => Some(Value::from(message.severity.to_string())),
+            Field::Text => Some(Value::from(message.text.as_str())),
+            Field::Context(key) => message
+                .lookup(key)
+                .filter(|value| !value.is_null())
+                .cloned(),
+        };
+
+        let (value, (op, operand)) = match (value, &self.op) {
+            (value, None) => return value.is_some(),
+            (None, Some((op, _))) => return *op == Operator::Ne || *op == Operator::NotMatch,
+            (Some(value), Some(op)) => (value, op),
+        };
+
+        let ordering = match operand {
+            Operand::Severity(severity) => Some(message.severity.cmp(severity)),
+            Operand::Regex(regex) => {
+                let is_match = regex.is_match(&value_to_string(&value));
+                return is_match == (*op == Operator::Match);
+            }
+            Operand::Value(operand) => compare(&value, operand),
+        };
+
+        match (op, ordering) {
+            (Operator::Eq, ordering) => ordering == Some(Ordering::Equal),
+            (Operator::Ne, ordering) => ordering != Some(Ordering::Equal),
+            (Operator::Lt, Some(ordering)) => ordering == Ordering::Less,
+            (Operator::Le, Some(ordering)) => ordering != Ordering::Greater,
+            (Operator::Gt, Some(ordering)) => ordering == Ordering::Greater,
+            (Operator::Ge, Some(ordering)) => ordering != Ordering::Less,
+            _ => false,
+        }
+    }
+}
+
+/// Compare a value with an operand, numerically if both are numbers.
+fn compare(value: &Value, operand: &str) -> Option<Ordering> {
+    let value = value_to_string(value);
+
+    match (value.parse::<f64>(), operand.parse::<f64>()) {
+        (Ok(value), Ok(operand)) => value.partial_cmp(&operand),
+        _ => Some(value.as_str().cmp(operand)),
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+regex_token!(struct OrToken = r"^\s*(\|\||or\b)");
+regex_token!(struct AndToken = r"^\s*(&&|and\b)");
+regex_token!(struct NotToken = r"^\s*(!|not\b)");
+regex_token!(struct OpenParen = r"^\s*\(");
+regex_token!(struct CloseParen = r"^\s*\)");
+regex_token!(struct Whitespace = r"^\s+");
+regex_token!(struct Name = r"^\s*[\w.@/-]+");
+regex_token!(struct OperatorToken = r"^\s*(!=|>=|<=|!~|=|<|>|~)");
+regex_token!(struct BareValue = r"^[^\s()]+");
+
+impl<'a> parse::Parse<'a> for Filter {
+    fn parse(buf: &mut ParseBuffer<'a>) -> parse::Result<Filter> {
+        let mut filters = vec![parse_and(buf)?];
+        while buf.parse::<Option<OrToken>>()?.is_some() {
+            filters.push(parse_and(buf)?);
+        }
+
+        Ok(flatten(filters, Filter::Any))
+    }
+}
+
+fn parse_and(buf: &mut ParseBuffer) -> parse::Result<Filter> {
+    let mut filters = vec![parse_unary(buf)?];
+    while buf.parse::<Option<AndToken>>()?.is_some() {
+        filters.push(parse_unary(buf)?);
+    }
+
+    Ok(flatten(filters, Filter::All))
+}
+
+fn parse_unary(buf: &mut ParseBuffer) -> parse::Result<Filter> {
+    if buf.parse::<Option<NotToken>>()?.is_some() {
+        Ok(Filter::Not(Box::new(parse_unary(buf)?)))
+    } else if buf.parse::<Option<OpenParen>>()?.is_some() {
+        let filter = buf.parse::<Filter>()?;
+        buf.parse::<CloseParen>()?;
+        Ok(filter)
+    } else {
+        parse_predicate(buf).map(Filter::Field)
+    }
+}
+
+fn parse_predicate(buf: &mut ParseBuffer) -> parse::Result<FieldPredicate> {
+    let name = buf.parse::<Name>()?;
+    let field = match name.as_ref().trim() {
+        "level" | "severity" => Field::Severity,
+        "msg" | "message" => Field::Text,
+        name => Field::Context(name.to_string()),
+    };
+
+    let op = match buf.parse::<Option<OperatorToken>>()? {
+        Some(op) => op,
+        None => return Ok(FieldPredicate { field, op: None }),
+    };
+
+    let op = match op.as_ref().trim() {
+        "=" => Operator::Eq,
+        "!=" => Operator::Ne,
+        "<" => Operator::Lt,
+        "<=" => Operator::Le,
+        ">" => Operator::Gt,
+        ">=" => Operator::Ge,
+        "~" => Operator::Match,
+        "!~" => Operator::NotMatch,
+        op => return Err(ParseError::custom(format!("Unknown operator {}", op))),
+    };
+
+    buf.parse::<Option<Whitespace>>()?;
+    let operand = if buf.is_next::<QuotedString>() {
+        buf.parse::<QuotedString>()?.0
+    } else {
+        buf.parse::<BareValue>()?.as_ref().to_string()
+    };
+
+    let operand = match (&field, op) {
+        (_, Operator::Match) | (_, Operator::NotMatch) => {
+            Operand::Regex(Regex::new(&operand).map_err(ParseError::custom)?)
+        }
+        (Field::Severity, _) => {
+            Operand::Severity(Severity::try_parse_str(&operand).map_err(ParseError::custom)?)
+        }
+        _ => Operand::Value(operand),
+    };
+
+    Ok(FieldPredicate {
+        field,
+        op: Some((op, operand)),
+    })
+}
+
+fn flatten(mut filters: Vec<Filter>, combine: fn(Vec<Filter>) -> Filter) -> Filter {
+    if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        combine(filters)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(text: &str, severity: Severity, context: Value) -> Message {
+        let mut message = Message::from_text(text, severity);
+        if let Value::Object(context) = context {
+            message.context = context.into_iter().collect();
+        }
+        message
+    }
+
+    fn matches(expr: &str, message: &Message) -> bool {
+        Filter::parse(expr).unwrap().matches(message)
+    }
+
+    #[test]
+    fn predicates() {
+        let message = message(
+            "request failed",
+            Severity::Error,
+            serde_json::json!({
+                "service": "api",
+                "status": 503,
+                "http": { "method": "GET" },
+            }),
+        );
+
+        assert!(matches("service=api", &message));
+        assert!(!matches("service!=api", &message));
+        assert!(matches("status>=500", &message));
+        assert!(!matches("status<500", &message));
+        assert!(matches("http.method=GET", &message));
+        assert!(matches("!trace_id", &message));
+        assert!(matches("level>=warn", &message));
+        assert!(matches(r#"msg~"^request (failed|ok)$""#, &message));
+        assert!(matches("trace_id!=x", &message));
+        assert!(matches("status >= 500 and service = \"api\"", &message));
+    }
+
+    #[test]
+    fn combinators() {
+        let message = message(
+            "hello",
+            Severity::Info,
+            serde_json::json!({ "service": "web" }),
+        );
+
+        assert!(matches("service=api or service=web", &message));
+        assert!(!matches("service=web && level>=error", &message));
+        assert!(matches("not (service=api || level<info)", &message));
+        assert!(matches(
+            "(service=web and msg~hel) or status>=500",
+            &message
+        ));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(Filter::parse("").is_err());
+        assert!(Filter::parse("a=").is_err());
+        assert!(Filter::parse("(a=b").is_err());
+        assert!(Filter::parse("level>=loud").is_err());
+        assert!(Filter::parse("a=b or").is_err());
+    }
+}
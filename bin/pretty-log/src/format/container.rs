@@ -0,0 +1,252 @@
+use chrono::DateTime;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use super::{text::TextFormat, InputFormat};
+use crate::message::{Message, Severity};
+
+/// Container logs written by Docker's `json-file` logging driver, such as
+/// `{"log":"started\n","stream":"stdout","time":"2020-03-01T12:00:00.123456789Z"}`.
+///
+/// Lines longer than 16 KiB are split into several entries, where all but the
+/// last lack the trailing newline.
+pub struct DockerFormat<T: ?Sized = TextFormat> {
+    inner_format: T,
+}
+
+/// Container logs written by the kubelet through the Container Runtime
+/// Interface, such as `2020-03-01T12:00:00.123456789Z stderr F failed`.
+///
+/// Long lines are split into several entries, where all but the last are
+/// tagged `P` (partial) instead of `F` (full).
+pub struct CriFormat<T: ?Sized = TextFormat> {
+    inner_format: T,
+}
+
+impl<T> DockerFormat<T> {
+    pub fn new(inner_format: T) -> DockerFormat<T> {
+        DockerFormat { inner_format }
+    }
+}
+
+impl<T> CriFormat<T> {
+    pub fn new(inner_format: T) -> CriFormat<T> {
+        CriFormat { inner_format }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct DockerLine {
+    log: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<String>,
+}
+
+impl<T: InputFormat + ?Sized> InputFormat for DockerFormat<T> {
+    fn parse_message(&self, line: &str, default_severity: Severity) -> Option<Message> {
+        let line: DockerLine = serde_json::from_str(line).ok()?;
+        let time = line.time.as_deref();
+        let payload = line.log.strip_suffix('\n').unwrap_or(&line.log);
+
+        Some(unwrap_envelope(
+            &self.inner_format,
+            payload,
+            line.stream.as_deref(),
+            time,
+            default_severity,
+        ))
+    }
+
+    fn is_partial(&self, line: &str) -> bool {
+        serde_json::from_str::<DockerLine>(line).is_ok_and(|line| !line.log.ends_with('\n'))
+    }
+
+    fn stream(&self, line: &str) -> Option<String> {
+        serde_json::from_str::<DockerLine>(line).ok()?.stream
+    }
+
+    fn join_partial(&self, partial: &str, line: &str) -> Option<String> {
+        let partial: DockerLine = serde_json::from_str(partial).ok()?;
+        let mut line: DockerLine = serde_json::from_str(line).ok()?;
+        if partial.stream != line.stream {
+            return None;
+        }
+
+        line.log = partial.log + &line.log;
+        serde_json::to_string(&line).ok()
+    }
+
+    fn payload<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        let line: DockerLine = serde_json::from_str(line).ok()?;
+        let payload = line.log.strip_suffix('\n').unwrap_or(&line.log);
+        Some(Cow::Owned(payload.to_string()))
+    }
+}
+
+struct CriLine<'a> {
+    time: &'a str,
+    stream: &'a str,
+    tag: &'a str,
+    payload: &'a str,
+}
+
+impl CriLine<'_> {
+    fn parse(line: &str) -> Option<CriLine<'_>> {
+        lazy_static! {
+            static ref CRI_LINE: Regex =
+                Regex::new(r"^(\S+) (stdout|stderr) ([A-Z]+(?::\S*)?)(?: (.*))?$").unwrap();
+        }
+
+        let captures = CRI_LINE.captures(line)?;
+        let time = captures.get(1)?.as_str();
+        DateTime::parse_from_rfc3339(time).ok()?;
+
+        Some(CriLine {
+            time,
+            stream: captures.get(2)?.as_str(),
+            tag: captures.get(3)?.as_str(),
+            payload: captures.get(4).map_or("", |m| m.as_str()),
+        })
+    }
+
+    fn is_partial(&self) -> bool {
+        self.tag.split(':').any(|tag| tag == "P")
+    }
+}
+
+impl<T: InputFormat + ?Sized> InputFormat for CriFormat<T> {
+    fn parse_message(&self, line: &str, default_severity: Severity) -> Option<Message> {
+        let line = CriLine::parse(line)?;
+
+        Some(unwrap_envelope(
+            &self.inner_format,
+            line.payload,
+            Some(line.stream),
+            Some(line.time),
+            default_severity,
+        ))
+    }
+
+    fn is_partial(&self, line: &str) -> bool {
+        CriLine::parse(line).is_some_and(|line| line.is_partial())
+    }
+
+    fn stream(&self, line: &str) -> Option<String> {
+        CriLine::parse(line).map(|line| line.stream.to_string())
+    }
+
+    fn join_partial(&self, partial: &str, line: &str) -> Option<String> {
+        let partial = CriLine::parse(partial)?;
+        let line = CriLine::parse(line)?;
+        if partial.stream != line.stream {
+            return None;
+        }
+
+        Some(format!(
+            "{} {} {} {}{}",
+            line.time, line.stream, line.tag, partial.payload, line.payload
+        ))
+    }
+
+    fn payload<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        CriLine::parse(line).map(|line| Cow::Borrowed(line.payload))
+    }
+}
+
+/// Create a message from the payload of a container log line, mapping the
+/// output stream to a default severity like for the output of a command.
+fn unwrap_envelope<T: InputFormat + ?Sized>(
+    inner_format: &T,
+    payload: &str,
+    stream: Option<&str>,
+    time: Option<&str>,
+    default_severity: Severity,
+) -> Message {
+    let severity = match stream {
+        Some("stdout") => Severity::Info,
+        Some("stderr") => Severity::Error,
+        _ => default_severity,
+    };
+
+    let mut message = Message::from_text(payload, severity);
+    message.time = time
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(Into::into);
+    if let Some(stream) = stream {
+        message.add_context("stream", stream);
+    }
+
+    if let Some(inner_message) = inner_format.parse_message(payload, Severity::Default) {
+        message.merge_with(inner_message);
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::json::{FieldMapping, JsonFormat};
+    use serde_json::json;
+
+    #[test]
+    fn docker_lines() {
+        let format = DockerFormat::new(TextFormat::new());
+
+        let line =
+            r#"{"log":"listening\n","stream":"stderr","time":"2020-03-01T12:00:00.123456789Z"}"#;
+        let message = format.parse_message(line, Severity::Default).unwrap();
+        assert_eq!(message.text, "listening");
+        assert_eq!(message.severity, Severity::Error);
+        assert_eq!(message.context["stream"], json!("stderr"));
+        assert!(message.time.is_some());
+        assert!(!format.is_partial(line));
+        assert_eq!(format.payload(line).unwrap(), "listening");
+        assert_eq!(format.stream(line).as_deref(), Some("stderr"));
+
+        let partial = r#"{"log":"abc","stream":"stdout","time":"2020-03-01T12:00:00Z"}"#;
+        let rest = r#"{"log":"def\n","stream":"stdout","time":"2020-03-01T12:00:01Z"}"#;
+        assert!(format.is_partial(partial));
+        assert!(format.join_partial(partial, line).is_none());
+
+        let joined = format.join_partial(partial, rest).unwrap();
+        let message = format.parse_message(&joined, Severity::Default).unwrap();
+        assert_eq!(message.text, "abcdef");
+        assert_eq!(message.severity, Severity::Info);
+    }
+
+    #[test]
+    fn cri_lines() {
+        let format = CriFormat::new(JsonFormat::new(&FieldMapping::default(), TextFormat::new()));
+
+        let line =
+            r#"2020-03-01T12:00:00.123456789Z stdout F {"level":"warn","msg":"slow","ms":900}"#;
+        let message = format.parse_message(line, Severity::Default).unwrap();
+        assert_eq!(message.text, "slow");
+        assert_eq!(message.severity, Severity::Warning);
+        assert_eq!(message.context["ms"], json!(900));
+        assert_eq!(message.context["stream"], json!("stdout"));
+
+        let partial = "2020-03-01T12:00:00Z stderr P first ";
+        let rest = "2020-03-01T12:00:00Z stderr F second";
+        assert!(format.is_partial(partial));
+        assert!(!format.is_partial(rest));
+        assert_eq!(format.payload(rest).unwrap(), "second");
+        assert!(format.payload("not a cri line").is_none());
+
+        let joined = format.join_partial(partial, rest).unwrap();
+        let message = format.parse_message(&joined, Severity::Default).unwrap();
+        assert_eq!(message.text, "first second");
+        assert_eq!(message.severity, Severity::Error);
+
+        assert!(format
+            .parse_message("not a cri line", Severity::Default)
+            .is_none());
+        let empty = format.parse_message("2020-03-01T12:00:00Z stdout F", Severity::Default);
+        assert_eq!(empty.unwrap().text, "");
+    }
+}
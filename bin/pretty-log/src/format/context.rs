@@ -0,0 +1,149 @@
+use crate::message::{Message, Value};
+use crate::DisplayOptions;
+
+/// A context entry of a message, ready to be displayed.
+#[derive(Debug, PartialEq)]
+pub struct ContextEntry {
+    /// The key, with nested objects flattened into dotted keys.
+    pub key: String,
+
+    /// The value, with strings unquoted.
+    pub value: String,
+
+    /// The value as displayed, quoted if needed and truncated.
+    pub display: String,
+}
+
+/// Select the context entries of a message to display, in display order.
+///
+/// Keys listed in `--context-order` come first, in that order, followed by
+/// the remaining keys in sorted order. A key is shown if it (or one of its
+/// parents) is in `--include-context` (if given), and is not in
+/// `--exclude-context`.
+pub fn context_entries(message: &Message, opts: &DisplayOptions) -> Vec<ContextEntry> {
+    let mut entries = Vec::new();
+    for (key, value) in &message.context {
+        flatten(key.clone(), value, &mut entries);
+    }
+
+    let matches = |keys: &[String], key: &str| {
+        keys.iter()
+            .any(|k| key == k || (key.starts_with(k.as_str()) && key[k.len()..].starts_with('.')))
+    };
+    entries.retain(|(key, _)| {
+        (opts.include_context.is_empty() || matches(&opts.include_context, key))
+            && !matches(&opts.exclude_context, key)
+    });
+
+    let position = |key: &str| {
+        opts.context_order
+            .iter()
+            .position(|k| k == key)
+            .unwrap_or(opts.context_order.len())
+    };
+    entries.sort_by(|(a, _), (b, _)| (position(a), a).cmp(&(position(b), b)));
+
+    entries
+        .into_iter()
+        .map(|(key, value)| {
+            let (value, display) = match value {
+                Value::String(value) if needs_quotes(value) => {
+                    (value.clone(), Value::from(value.as_str()).to_string())
+                }
+                Value::String(value) => (value.clone(), value.clone()),
+                value => (value.to_string(), value.to_string()),
+            };
+
+            ContextEntry {
+                key,
+                value,
+                display: truncate(display, opts.context_width),
+            }
+        })
+        .collect()
+}
+
+fn flatten<'a>(key: String, value: &'a Value, entries: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (name, value) in object {
+                flatten(format!("{}.{}", key, name), value, entries);
+            }
+        }
+        value => entries.push((key, value)),
+    }
+}
+
+fn needs_quotes(value: &str) -> bool {
+    value.is_empty() || value.chars().any(|ch| ch.is_whitespace() || ch == '"')
+}
+
+fn truncate(value: String, max_width: Option<usize>) -> String {
+    match max_width {
+        Some(max_width) if max_width > 0 && value.chars().count() > max_width => {
+            let mut value: String = value.chars().take(max_width - 1).collect();
+            value.push('…');
+            value
+        }
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Severity;
+    use structopt::StructOpt;
+
+    fn display_entries(args: &[&str]) -> Vec<(String, String)> {
+        let mut message = Message::from_text("hello", Severity::Info);
+        message.add_context("service", "api");
+        message.add_context("status", 503);
+        message.add_context("error", "connection reset by peer");
+        message.add_context("http", serde_json::json!({ "method": "GET", "path": "/" }));
+
+        let args = std::iter::once("test").chain(args.iter().cloned());
+        let opts = DisplayOptions::from_iter(args);
+        context_entries(&message, &opts)
+            .into_iter()
+            .map(|entry| (entry.key, entry.display))
+            .collect()
+    }
+
+    fn keys(entries: &[(String, String)]) -> Vec<&str> {
+        entries.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    #[test]
+    fn order_and_format() {
+        let entries = display_entries(&["--context-order", "status,service"]);
+        assert_eq!(
+            entries,
+            vec![
+                ("status".to_string(), "503".to_string()),
+                ("service".to_string(), "api".to_string()),
+                (
+                    "error".to_string(),
+                    "\"connection reset by peer\"".to_string()
+                ),
+                ("http.method".to_string(), "GET".to_string()),
+                ("http.path".to_string(), "/".to_string()),
+            ]
+        );
+
+        let entries = display_entries(&["--context-width", "10"]);
+        assert_eq!(entries[0].1, "\"connecti…");
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        assert_eq!(
+            keys(&display_entries(&["--include-context", "http,status"])),
+            vec!["http.method", "http.path", "status"]
+        );
+        assert_eq!(
+            keys(&display_entries(&["--exclude-context", "http.path,error"])),
+            vec!["http.method", "service", "status"]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use serde_json::Deserializer;
+
+use super::style::Element;
+use crate::message::Value;
+
+/// A part of a message text.
+#[derive(Debug, PartialEq)]
+pub enum Block<'a> {
+    Text(&'a str),
+    Json(Value),
+}
+
+/// Split a message text into plain text and embedded JSON objects or arrays.
+///
+/// Only objects, and arrays that contain objects or arrays, are taken to be
+/// JSON, so that text such as `[1]` or `[INFO]` is left alone.
+pub fn split_json(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+
+    while let Some((start, end, value)) = find_json(rest) {
+        let before = rest[..start].trim();
+        if !before.is_empty() {
+            blocks.push(Block::Text(before));
+        }
+        blocks.push(Block::Json(value));
+        rest = &rest[end..];
+    }
+
+    let rest = rest.trim();
+    if !rest.is_empty() || blocks.is_empty() {
+        blocks.push(Block::Text(rest));
+    }
+
+    blocks
+}
+
+fn find_json(text: &str) -> Option<(usize, usize, Value)> {
+    text.match_indices(&['{', '['][..]).find_map(|(start, _)| {
+        let mut values = Deserializer::from_str(&text[start..]).into_iter::<Value>();
+        let value = values.next()?.ok()?;
+        let is_structured = match &value {
+            Value::Object(object) => !object.is_empty(),
+            Value::Array(array) => array
+                .iter()
+                .any(|value| value.is_object() || value.is_array()),
+            _ => false,
+        };
+
+        if is_structured {
+            Some((start, start + values.byte_offset(), value))
+        } else {
+            None
+        }
+    })
+}
+
+/// A line of styled text.
+pub type Line = Vec<(Element, String)>;
+
+/// Format a JSON value as indented lines, with keys, values and punctuation
+/// styled as context keys, context values and separators.
+pub fn json_lines(value: &Value) -> Vec<Line> {
+    let mut lines = vec![Vec::new()];
+    write_value(value, 0, &mut lines);
+    lines
+}
+
+fn write_value(value: &Value, indent: usize, lines: &mut Vec<Line>) {
+    fn push(lines: &mut [Line], element: Element, text: impl Into<String>) {
+        lines.last_mut().unwrap().push((element, text.into()));
+    }
+
+    fn new_line(lines: &mut Vec<Line>, indent: usize) {
+        lines.push(vec![(Element::Separator, "  ".repeat(indent))]);
+    }
+
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            push(lines, Element::Separator, "{");
+            for (index, (key, value)) in object.iter().enumerate() {
+                new_line(lines, indent + 1);
+                push(
+                    lines,
+                    Element::ContextKey,
+                    Value::from(key.as_str()).to_string(),
+                );
+                push(lines, Element::Separator, ": ");
+                write_value(value, indent + 1, lines);
+                if index + 1 < object.len() {
+                    push(lines, Element::Separator, ",");
+                }
+            }
+            new_line(lines, indent);
+            push(lines, Element::Separator, "}");
+        }
+        Value::Array(array) if !array.is_empty() => {
+            push(lines, Element::Separator, "[");
+            for (index, value) in array.iter().enumerate() {
+                new_line(lines, indent + 1);
+                write_value(value, indent + 1, lines);
+                if index + 1 < array.len() {
+                    push(lines, Element::Separator, ",");
+                }
+            }
+            new_line(lines, indent);
+            push(lines, Element::Separator, "]");
+        }
+        value => push(lines, Element::ContextValue, value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn split_text() {
+        assert_eq!(
+            split_json(r#"got response {"status": 200, "body": []} in 5ms"#),
+            vec![
+                Block::Text("got response"),
+                Block::Json(json!({ "status": 200, "body": [] })),
+                Block::Text("in 5ms"),
+            ]
+        );
+        assert_eq!(
+            split_json("[INFO] {} [1, 2] {broken"),
+            vec![Block::Text("[INFO] {} [1, 2] {broken")]
+        );
+        assert_eq!(
+            split_json(r#"[{"id": 1}]"#),
+            vec![Block::Json(json!([{ "id": 1 }]))]
+        );
+    }
+
+    #[test]
+    fn format_lines() {
+        let lines: Vec<String> = json_lines(&json!({ "a": [1, {}], "b": "x" }))
+            .into_iter()
+            .map(|line| line.into_iter().map(|(_, text)| text).collect())
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "{",
+                "  \"a\": [",
+                "    1,",
+                "    {}",
+                "  ],",
+                "  \"b\": \"x\"",
+                "}"
+            ]
+        );
+    }
+}
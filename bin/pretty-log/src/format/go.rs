@@ -2,8 +2,16 @@ use chrono::DateTime;
 
 use super::{text::TextFormat, InputFormat};
 use crate::message::{Message, Severity};
-use crate::parse::{self, parse, Parse, ParseBuffer, ParseError, Punctuated, Token};
-
+use crate::parse::{
+    self, parse, regex_token, Parse, ParseBuffer, ParseError, Punctuated, QuotedString,
+};
+
+/// Lines written by the text formatter of logrus, such as
+/// `time="2020-03-01T12:00:00Z" level=info msg="started" port=8080`.
+///
+/// Only lines with the `time`, `level` and `msg` fields that logrus always
+/// writes are accepted, so that other `key=value` lines are left to the
+/// `logfmt` format.
 pub struct GoFormat<T: ?Sized = TextFormat> {
     inner_format: T,
 }
@@ -18,6 +26,11 @@ impl<T: InputFormat + ?Sized> InputFormat for GoFormat<T> {
     fn parse_message(&self, message: &str, default_severity: Severity) -> Option<Message> {
         let fields: Punctuated<(RawLiteral, Equals, Value), Whitespace> = parse(message).ok()?;
 
+        let has_field = |key: &str| fields.items().any(|(name, _, _)| name.as_ref() == key);
+        if !["time", "level", "msg"].iter().all(|key| has_field(key)) {
+            return None;
+        }
+
         let mut message: Message = Message::from_text("", default_severity);
 
         for (name, _, value) in fields.items() {
@@ -32,9 +45,9 @@ impl<T: InputFormat + ?Sized> InputFormat for GoFormat<T> {
 
                 "msg" => message.text = value.to_string(),
 
-                "level" => match parse_severity(value) {
-                    Some(severity) => message.severity = severity,
-                    None => message.add_context(name, value),
+                "level" => match Severity::try_parse_str(value) {
+                    Ok(severity) => message.severity = severity,
+                    Err(_) => message.add_context(name, value),
                 },
 
                 _ => message.add_context(name, value),
@@ -52,107 +65,20 @@ impl<T: InputFormat + ?Sized> InputFormat for GoFormat<T> {
     }
 }
 
-fn parse_severity(value: &str) -> Option<Severity> {
-    match value {
-        "debug" => Some(Severity::Debug),
-        "info" => Some(Severity::Info),
-        "warning" => Some(Severity::Warning),
-        "error" => Some(Severity::Error),
-
-        _ => None,
-    }
-}
-
-macro_rules! regex_token {
-    ($vis:vis struct $name:ident = $pattern:literal) => {
-        #[derive(Debug)]
-        $vis struct $name<'a>(&'a str);
-
-        impl<'a> AsRef<str> for $name<'a> {
-            fn as_ref(&self) -> &str {
-                self.0
-            }
-        }
-
-        impl<'a> Token<'a> for $name<'a> {
-            fn parse_token(s: &'a str) -> parse::Result<(Self, &'a str)> {
-                use lazy_static::lazy_static;
-                use regex::Regex;
-
-                lazy_static!{
-                    static ref RE: Regex = Regex::new($pattern).unwrap();
-                }
-
-                RE.find(s)
-                    .and_then(|m| {
-                        if m.start() == 0 && m.end() > 0 {
-                            Some((Self(m.as_str()), &s[m.end()..]))
-                        } else {
-                            None
-                        }
-                    })
-                    .ok_or_else(|| ParseError::custom(format!("Could not match token {}", stringify!($name))))
-            }
-        }
-    }
-}
-
 regex_token!(struct Whitespace = r"[ ]+");
 regex_token!(struct RawLiteral = r"[/a-zA-Z0-9_-]+");
 regex_token!(struct Equals = r"=");
 
-#[derive(Debug)]
-struct StringLiteral(String);
-
-impl AsRef<str> for StringLiteral {
-    fn as_ref(&self) -> &str {
-        &self.0
-    }
-}
-
-impl<'a> Token<'a> for StringLiteral {
-    fn parse_token(s: &'a str) -> parse::Result<(StringLiteral, &'a str)> {
-        let mut iter = s.chars();
-
-        match iter.next() {
-            Some('"') => (),
-            _ => return Err(ParseError::custom("Invalid string literal")),
-        }
-
-        let mut s = String::new();
-        while let Some(ch) = iter.next() {
-            match ch {
-                '"' => return Ok((StringLiteral(s), iter.as_str())),
-                '\\' => match iter.next() {
-                    Some('"') => s.push('"'),
-                    Some('\\') => s.push('\\'),
-                    Some('n') => s.push('\n'),
-                    Some('t') => s.push('\t'),
-                    Some(ch) => {
-                        eprintln!("unknown escape sequence: \\{}", ch);
-                        s.push('\\');
-                        s.push(ch);
-                    }
-                    None => return Err(ParseError::custom("Invalid string literal")),
-                },
-                _ => s.push(ch),
-            }
-        }
-
-        Err(ParseError::custom("Invalid string literal"))
-    }
-}
-
 #[derive(Debug)]
 enum Value<'a> {
-    String(StringLiteral),
+    String(QuotedString),
     Raw(RawLiteral<'a>),
 }
 
 impl<'a> AsRef<str> for Value<'a> {
     fn as_ref(&self) -> &str {
         match self {
-            Value::String(lit) => lit.as_ref(),
+            Value::String(lit) => &lit.0,
             Value::Raw(lit) => lit.as_ref(),
         }
     }
@@ -160,8 +86,8 @@ impl<'a> AsRef<str> for Value<'a> {
 
 impl<'a> Parse<'a> for Value<'a> {
     fn parse(buf: &mut ParseBuffer<'a>) -> parse::Result<Self> {
-        if buf.is_next::<StringLiteral>() {
-            StringLiteral::parse(buf).map(Value::String)
+        if buf.is_next::<QuotedString>() {
+            QuotedString::parse(buf).map(Value::String)
         } else if buf.is_next::<RawLiteral>() {
             RawLiteral::parse(buf).map(Value::Raw)
         } else {
@@ -0,0 +1,285 @@
+use chrono::{Local, TimeZone};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Map;
+use std::collections::HashMap;
+
+use super::{syslog::facility_name, text::TextFormat, InputFormat};
+use crate::message::{Message, Severity, Value};
+
+/// Journal entries written by `journalctl -o json` (one object per line) or
+/// `journalctl -o export` (one `KEY=value` line per field, with entries
+/// separated by blank lines).
+///
+/// Well-known fields are mapped to the same context keys as for syslog lines,
+/// other fields are added to the context as they are, and trusted fields
+/// (starting with `_`) are left out.
+pub struct JournaldFormat<T: ?Sized = TextFormat> {
+    inner_format: T,
+}
+
+impl<T> JournaldFormat<T> {
+    pub fn new(inner_format: T) -> JournaldFormat<T> {
+        JournaldFormat { inner_format }
+    }
+}
+
+impl<T: InputFormat + ?Sized> InputFormat for JournaldFormat<T> {
+    fn parse_message(&self, record: &str, default_severity: Severity) -> Option<Message> {
+        let fields = if record.starts_with('{') {
+            json_fields(record)?
+        } else {
+            match export_fields(record)? {
+                (fields, true) => fields,
+                (_, false) => return None,
+            }
+        };
+
+        let mut message = to_message(fields, default_severity);
+        if let Some(inner_message) = self
+            .inner_format
+            .parse_message(&message.text, Severity::Default)
+        {
+            message.merge_with(inner_message);
+        }
+
+        Some(message)
+    }
+
+    fn is_partial(&self, record: &str) -> bool {
+        matches!(export_fields(record), Some((_, false)))
+    }
+
+    fn join_partial(&self, partial: &str, line: &str) -> Option<String> {
+        export_fields(partial)?;
+        Some(format!("{}\n{}", partial, line))
+    }
+}
+
+fn json_fields(line: &str) -> Option<HashMap<String, String>> {
+    let object: Map<String, Value> = serde_json::from_str(line).ok()?;
+
+    // binary values are arrays of bytes, and fields with several values are
+    // arrays of values, of which the last one is used
+    fn to_string(value: Value) -> Option<String> {
+        match value {
+            Value::String(value) => Some(value),
+            Value::Array(values) if values.iter().all(Value::is_u64) => {
+                let bytes: Vec<u8> = values
+                    .iter()
+                    .filter_map(|byte| byte.as_u64().map(|byte| byte as u8))
+                    .collect();
+                Some(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            Value::Array(values) => values.into_iter().last().and_then(to_string),
+            _ => None,
+        }
+    }
+
+    Some(
+        object
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, to_string(value)?)))
+            .collect(),
+    )
+}
+
+/// Parse an entry in the journal export format, and check whether it is
+/// complete (ends with a blank line) or not.
+///
+/// Returns `None` if the text does not start with a `KEY=value` line.
+fn export_fields(record: &str) -> Option<(HashMap<String, String>, bool)> {
+    lazy_static! {
+        static ref FIELD: Regex = Regex::new(r"^[A-Z_][A-Z0-9_]*=").unwrap();
+        static ref BINARY_FIELD: Regex = Regex::new(r"^[A-Z_][A-Z0-9_]*$").unwrap();
+    }
+
+    if !FIELD.is_match(record) {
+        return None;
+    }
+
+    // lines are joined without their newlines, so the blank line that ends
+    // an entry leaves a trailing newline
+    let mut fields = HashMap::new();
+    let mut rest = record;
+    while let Some((line, next)) = rest.split_once('\n') {
+        if let Some((key, value)) = line.split_once('=') {
+            fields.insert(key.to_string(), value.to_string());
+            rest = next;
+        } else if BINARY_FIELD.is_match(line) {
+            match binary_value(next) {
+                Some((value, next)) => {
+                    fields.insert(line.to_string(), value.to_string());
+                    rest = next;
+                }
+                None => break,
+            }
+        } else {
+            return None;
+        }
+
+        if rest.is_empty() {
+            return Some((fields, true));
+        }
+    }
+
+    Some((fields, false))
+}
+
+/// Split the value of a binary field, which is prefixed by its length as a
+/// little-endian 64-bit integer and followed by a newline, from the rest of an
+/// entry. Returns `None` if the value is not complete.
+///
+/// Lengths with bytes outside of ASCII are mangled when the input is read as
+/// text, in which case the value is taken to end with the first newline that
+/// is followed by another field or by the end of the entry.
+fn binary_value(s: &str) -> Option<(&str, &str)> {
+    lazy_static! {
+        static ref MANGLED_LENGTH: Regex = Regex::new(r"(?s)^.{1,3}\x00{5}").unwrap();
+        static ref NEXT_FIELD: Regex = Regex::new(r"\n(?:$|[A-Z_][A-Z0-9_]*[=\n])").unwrap();
+    }
+
+    let prefix = s.as_bytes().get(..8)?;
+    if prefix.is_ascii() {
+        let mut length = [0; 8];
+        length.copy_from_slice(prefix);
+        let length = u64::from_le_bytes(length) as usize;
+
+        let value = s[8..].get(..length)?;
+        let rest = s[8 + length..].strip_prefix('\n')?;
+        Some((value, rest))
+    } else {
+        let s = &s[MANGLED_LENGTH.find(s)?.end()..];
+        let end = NEXT_FIELD.find(s)?.start();
+        Some((&s[..end], &s[end + 1..]))
+    }
+}
+
+fn to_message(mut fields: HashMap<String, String>, default_severity: Severity) -> Message {
+    let mut message = Message::from_text("", default_severity);
+
+    if let Some(text) = fields.remove("MESSAGE") {
+        message.text = text;
+    }
+    if let Some(priority) = fields.remove("PRIORITY") {
+        if let Ok(priority) = priority.parse() {
+            message.severity = Severity::from_syslog(priority);
+        }
+    }
+    if let Some(facility) = fields.remove("SYSLOG_FACILITY") {
+        match facility.parse().ok().and_then(facility_name) {
+            Some(name) => message.add_context("facility", name),
+            None => message.add_context("facility", facility),
+        }
+    }
+
+    let time = fields
+        .get("_SOURCE_REALTIME_TIMESTAMP")
+        .or_else(|| fields.get("__REALTIME_TIMESTAMP"));
+    if let Some(micros) = time.and_then(|time| time.parse::<i64>().ok()) {
+        message.time = Local.timestamp_micros(micros).single();
+    }
+
+    let mappings = [
+        ("hostname", &["_HOSTNAME"][..]),
+        ("app", &["SYSLOG_IDENTIFIER", "_COMM"][..]),
+        ("pid", &["SYSLOG_PID", "_PID"][..]),
+        ("unit", &["_SYSTEMD_UNIT", "_SYSTEMD_USER_UNIT"][..]),
+    ];
+    for (name, keys) in mappings {
+        if let Some(value) = keys.iter().find_map(|&key| fields.remove(key)) {
+            message.add_context(name, value);
+        }
+    }
+
+    for (key, value) in fields {
+        if !key.starts_with('_') && !key.starts_with("SYSLOG_") {
+            message.add_context(key, value);
+        }
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_entries() {
+        let format = JournaldFormat::new(TextFormat::new());
+
+        let line = json!({
+            "__CURSOR": "s=1",
+            "__REALTIME_TIMESTAMP": "1583064000123456",
+            "_HOSTNAME": "web",
+            "_PID": "812",
+            "_COMM": "sshd",
+            "_BOOT_ID": "abc",
+            "_SYSTEMD_UNIT": "ssh.service",
+            "PRIORITY": "3",
+            "SYSLOG_FACILITY": "4",
+            "SYSLOG_IDENTIFIER": "sshd",
+            "CODE_LINE": ["1", "2"],
+            "MESSAGE": [104, 105, 10, 33],
+        })
+        .to_string();
+
+        let message = format.parse_message(&line, Severity::Default).unwrap();
+        assert_eq!(message.text, "hi\n!");
+        assert_eq!(message.severity, Severity::Error);
+        assert_eq!(
+            message.time,
+            Local.timestamp_micros(1583064000123456).single()
+        );
+
+        let mut keys: Vec<_> = message.context.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            vec!["CODE_LINE", "app", "facility", "hostname", "pid", "unit"]
+        );
+        assert_eq!(message.context["facility"], json!("auth"));
+        assert_eq!(message.context["app"], json!("sshd"));
+        assert_eq!(message.context["CODE_LINE"], json!("2"));
+    }
+
+    #[test]
+    fn export_entries() {
+        let format = JournaldFormat::new(TextFormat::new());
+
+        let mut record = String::new();
+        for line in &["__CURSOR=s=1", "PRIORITY=4", "MESSAGE"] {
+            assert!(record.is_empty() || format.is_partial(&record));
+            record = match format.join_partial(&record, line) {
+                Some(record) => record,
+                None => line.to_string(),
+            };
+        }
+
+        // the length prefix of a binary value may contain a newline
+        let value = "two\nlines!";
+        let mut rest = String::from_utf8((value.len() as u64).to_le_bytes().to_vec()).unwrap();
+        rest.push_str(value);
+        for line in rest.split('\n').chain(vec!["CODE_FILE=main.c", ""]) {
+            assert!(format.is_partial(&record));
+            record = format.join_partial(&record, line).unwrap();
+        }
+
+        assert!(!format.is_partial(&record));
+        let message = format.parse_message(&record, Severity::Default).unwrap();
+        assert_eq!(message.text, "two\nlines!");
+        assert_eq!(message.severity, Severity::Warning);
+        assert_eq!(message.context["CODE_FILE"], json!("main.c"));
+
+        let mangled = "MESSAGE=x\nDATA\n\u{fffd}\0\0\0\0\0\0\0a\nb\nNEXT=1\n";
+        let (fields, complete) = export_fields(mangled).unwrap();
+        assert!(complete);
+        assert_eq!(fields["DATA"], "a\nb");
+        assert_eq!(fields["NEXT"], "1");
+
+        assert!(!format.is_partial("hello world"));
+        assert!(format.join_partial("hello", "world").is_none());
+    }
+}
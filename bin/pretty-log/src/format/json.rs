@@ -1,29 +1,32 @@
-use chrono::{DateTime, Local, TimeZone};
-use serde::{
-    de::{self, Deserializer},
-    Deserialize,
-};
-use std::collections::HashMap;
-
-use super::{text::TextFormat, InputFormat};
-use crate::message::{Message, Severity};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
+use serde::{ser::SerializeMap, Deserialize, Serializer};
+use serde_json::Map;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use super::{text::TextFormat, InputFormat, OutputFormat};
+use crate::message::{Message, Severity, Value};
 use crate::utils::StringOrNumber;
 
 pub struct JsonFormat<T: ?Sized = TextFormat> {
+    fields: FieldMapping,
     inner_format: T,
 }
 
 impl<T> JsonFormat<T> {
-    pub fn new(inner_format: T) -> JsonFormat<T> {
-        JsonFormat { inner_format }
+    pub fn new(fields: &FieldMapping, inner_format: T) -> JsonFormat<T> {
+        JsonFormat {
+            fields: fields.clone(),
+            inner_format,
+        }
     }
 }
 
 impl<T: InputFormat + ?Sized> InputFormat for JsonFormat<T> {
     fn parse_message(&self, message: &str, default_severity: Severity) -> Option<Message> {
-        let message: JsonMessage = serde_json::from_str(message).ok()?;
+        let object: Map<String, Value> = serde_json::from_str(message).ok()?;
 
-        let mut message = message.to_message(default_severity);
+        let mut message = self.fields.to_message(object, default_severity)?;
         if let Some(inner_message) = self
             .inner_format
             .parse_message(&message.text, Severity::Default)
@@ -35,35 +38,187 @@ impl<T: InputFormat + ?Sized> InputFormat for JsonFormat<T> {
     }
 }
 
-#[derive(Deserialize)]
-struct JsonMessage {
-    pub message: String,
-    pub severity: Option<Severity>,
+impl<T: Send + Sync + ?Sized> OutputFormat for JsonFormat<T> {
+    fn print_message<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        message: &Message,
+    ) -> io::Result<()> {
+        let mut serializer = serde_json::Serializer::new(&mut *writer);
+        self.fields.write_message(&mut serializer, message)?;
+        writeln!(writer)
+    }
+}
+
+/// Candidate keys for each of the fields of a [`Message`].
+///
+/// For each field, the first key that is present in a JSON object is used.
+/// Keys may be dotted paths (such as `log.level`) to refer to nested objects.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct FieldMapping {
+    pub message: Vec<String>,
+    pub severity: Vec<String>,
+    pub time: Vec<String>,
+
+    /// Keys of objects whose members are added to the message context.
+    pub context: Vec<String>,
+}
+
+impl Default for FieldMapping {
+    fn default() -> FieldMapping {
+        fn keys(keys: &[&str]) -> Vec<String> {
+            keys.iter().map(|key| key.to_string()).collect()
+        }
+
+        FieldMapping {
+            message: keys(&["message", "msg", "@message"]),
+            severity: keys(&["severity", "level", "lvl", "log.level"]),
+            time: keys(&["time", "timestamp", "ts", "@timestamp"]),
+            context: keys(&["context"]),
+        }
+    }
+}
+
+impl FieldMapping {
+    fn to_message(
+        &self,
+        mut object: Map<String, Value>,
+        default_severity: Severity,
+    ) -> Option<Message> {
+        let text = match take_first(&mut object, &self.message)?.1 {
+            Value::String(text) => text,
+            value => value.to_string(),
+        };
+
+        let mut message = Message::from_text(text, default_severity);
+
+        if let Some((key, severity)) = take_first(&mut object, &self.severity) {
+            // unknown levels are kept, rather than lost
+            let parsed = match &severity {
+                Value::String(name) => Severity::try_parse_str(name).ok(),
+                Value::Number(number) => number.as_u64().map(Severity::parse_u64),
+                _ => None,
+            };
+            match parsed {
+                Some(parsed) => message.severity = parsed.or(default_severity),
+                None => message.add_context(key, severity),
+            }
+        }
+
+        if let Some((key, time)) = take_first(&mut object, &self.time) {
+            match parse_time(&time) {
+                Some(time) => message.time = Some(time),
+                None => message.add_context(key, time),
+            }
+        }
+
+        for key in &self.context {
+            match take_path(&mut object, key) {
+                Some(Value::Object(context)) => {
+                    for (name, value) in context {
+                        message.add_context(name, value);
+                    }
+                }
+                Some(value) => message.add_context(key, value),
+                None => (),
+            }
+        }
+
+        for (name, value) in object {
+            message.add_context(name, value);
+        }
+
+        Some(message)
+    }
+}
+
+impl FieldMapping {
+    /// Serialize a message as a JSON object, using the first candidate key for
+    /// each field so that the object can be read back with the same mapping.
+    ///
+    /// If there are no context keys, the context is flattened into the object.
+    fn write_message<S: Serializer>(
+        &self,
+        serializer: S,
+        message: &Message,
+    ) -> Result<S::Ok, S::Error> {
+        let context: BTreeMap<_, _> = message.context.iter().collect();
+
+        let mut map = serializer.serialize_map(None)?;
+
+        if let (Some(key), Some(time)) = (self.time.first(), message.time) {
+            map.serialize_entry(key, &time.to_rfc3339_opts(SecondsFormat::AutoSi, false))?;
+        }
+
+        if let Some(key) = self.severity.first() {
+            map.serialize_entry(key, &message.severity.to_string().to_lowercase())?;
+        }
+
+        if let Some(key) = self.message.first() {
+            map.serialize_entry(key, &message.text)?;
+        }
 
-    #[serde(alias = "timestamp", default, deserialize_with = "parse_time")]
-    pub time: Option<DateTime<Local>>,
+        match self.context.first() {
+            Some(_) if context.is_empty() => (),
+            Some(key) => map.serialize_entry(key, &context)?,
+            None => {
+                for (name, value) in context {
+                    map.serialize_entry(name, value)?;
+                }
+            }
+        }
 
-    #[serde(default)]
-    pub context: HashMap<String, String>,
+        if let Some(repeat) = &message.repeat {
+            map.serialize_entry("repeated", &repeat.count)?;
+            if let Some(time) = repeat.last_time {
+                map.serialize_entry(
+                    "last_time",
+                    &time.to_rfc3339_opts(SecondsFormat::AutoSi, false),
+                )?;
+            }
+        }
+
+        map.end()
+    }
 }
 
-impl JsonMessage {
-    pub fn to_message(self, default_severity: Severity) -> Message {
-        Message {
-            text: self.message,
-            severity: self.severity.unwrap_or(default_severity),
-            time: self.time,
-            context: self.context,
+/// Remove and return the first of `keys` present in `object`, with its value.
+fn take_first<'a>(object: &mut Map<String, Value>, keys: &'a [String]) -> Option<(&'a str, Value)> {
+    keys.iter()
+        .find_map(|key| Some((key.as_str(), take_path(object, key)?)))
+}
+
+/// Remove and return the value at `path` in `object`.
+///
+/// A key containing dots is first looked up literally, and otherwise as a path
+/// into nested objects. Nested objects that become empty are removed.
+fn take_path(object: &mut Map<String, Value>, path: &str) -> Option<Value> {
+    if let Some(value) = object.remove(path) {
+        return Some(value);
+    }
+
+    let (head, rest) = split_path(path)?;
+    let value = match object.get_mut(head) {
+        Some(Value::Object(nested)) => take_path(nested, rest)?,
+        _ => return None,
+    };
 
-            ..Default::default()
+    if let Some(Value::Object(nested)) = object.get(head) {
+        if nested.is_empty() {
+            object.remove(head);
         }
     }
+
+    Some(value)
+}
+
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    let index = path.find('.')?;
+    Some((&path[..index], &path[index + 1..]))
 }
 
-fn parse_time<'de, D>(deserializer: D) -> Result<Option<DateTime<Local>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
+fn parse_time(value: &Value) -> Option<DateTime<Local>> {
     #[derive(Debug, Deserialize)]
     #[serde(untagged)]
     enum TimestampVariants {
@@ -72,15 +227,110 @@ where
             seconds: StringOrNumber<i64>,
             nanos: StringOrNumber<u32>,
         },
-        Any(serde_json::Value),
+        Epoch(f64),
+    }
+
+    match TimestampVariants::deserialize(value).ok()? {
+        TimestampVariants::DateTime(datetime) => Some(datetime),
+        TimestampVariants::SecondsNanos { seconds, nanos } => Local
+            .timestamp_opt(seconds.into_number().ok()?, nanos.into_number().ok()?)
+            .single(),
+        TimestampVariants::Epoch(epoch) => {
+            // guess the unit of epoch timestamps from their magnitude
+            let nanos = if epoch.abs() >= 1e17 {
+                epoch
+            } else if epoch.abs() >= 1e14 {
+                epoch * 1e3
+            } else if epoch.abs() >= 1e11 {
+                epoch * 1e6
+            } else {
+                epoch * 1e9
+            };
+
+            Some(Local.timestamp_nanos(nanos.round() as i64))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(line: &str) -> Message {
+        JsonFormat::new(&FieldMapping::default(), TextFormat::new())
+            .parse_message(line, Severity::Info)
+            .expect("valid message")
+    }
+
+    #[test]
+    fn map_fields() {
+        let message = parse(r#"{"msg":"hello","level":40,"ts":1583064000.5,"pid":1}"#);
+        assert_eq!(message.text, "hello");
+        assert_eq!(message.severity, Severity::Warning);
+        assert_eq!(message.time.unwrap().timestamp_millis(), 1_583_064_000_500);
+        assert_eq!(message.context.get("pid"), Some(&Value::from(1)));
+
+        let message = parse(
+            r#"{"message":"ecs","@timestamp":"2020-03-01T12:00:00Z","log":{"level":"warn","logger":"x"}}"#,
+        );
+        assert_eq!(message.severity, Severity::Warning);
+        assert!(message.time.is_some());
+        assert_eq!(
+            message.context.get("log"),
+            Some(&serde_json::json!({ "logger": "x" }))
+        );
+    }
+
+    #[test]
+    fn severity_values() {
+        let message = parse(r#"{"msg":"x","level":"verbose"}"#);
+        assert_eq!(message.severity, Severity::Info);
+        assert_eq!(message.context.get("level"), Some(&Value::from("verbose")));
+        assert!(!message.context.contains_key("severity"));
+
+        let message = parse(r#"{"msg":"x","level":{"name":"warn"}}"#);
+        assert_eq!(message.severity, Severity::Info);
+        assert!(message.context.contains_key("level"));
+
+        let message = parse(r#"{"msg":"x","ts":"yesterday"}"#);
+        assert!(message.time.is_none());
+        assert_eq!(message.context.get("ts"), Some(&Value::from("yesterday")));
+
+        // bunyan levels between the named ones round down, as strings too
+        for level in &["45", r#""45""#] {
+            let message = parse(&format!(r#"{{"msg":"x","level":{}}}"#, level));
+            assert_eq!(message.severity, Severity::Warning);
+        }
+        assert_eq!(Severity::try_parse_str("70"), Ok(Severity::Critical));
+        assert_eq!(Severity::parse_u64(3), Severity::Notice);
+    }
+
+    #[test]
+    fn round_trip() {
+        let line = r#"{"msg":"hello","level":"warn","time":"2020-03-01T12:00:00.250Z","http":{"status":503}}"#;
+        let message = parse(line);
+
+        let fields = FieldMapping::default();
+        let format = JsonFormat::new(&fields, TextFormat::new());
+
+        let mut output = Vec::new();
+        format.print_message(&mut output, &message).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with('\n'));
+
+        let parsed = parse(output.trim_end());
+        assert_eq!(parsed.text, message.text);
+        assert_eq!(parsed.severity, message.severity);
+        assert_eq!(parsed.time, message.time);
+        assert_eq!(parsed.context, message.context);
     }
 
-    match <Option<TimestampVariants> as Deserialize>::deserialize(deserializer) {
-        Ok(Some(TimestampVariants::DateTime(datetime))) => Ok(Some(datetime)),
-        Ok(Some(TimestampVariants::SecondsNanos { seconds, nanos })) => Ok(Some(Local.timestamp(
-            seconds.into_number().map_err(de::Error::custom)?,
-            nanos.into_number().map_err(de::Error::custom)?,
-        ))),
-        _ => Ok(None),
+    #[test]
+    fn flatten_context() {
+        let message = parse(r#"{"message":"x","context":{"a":"b"},"c":"d"}"#);
+        assert_eq!(message.severity, Severity::Info);
+        assert_eq!(message.context.get("a").and_then(Value::as_str), Some("b"));
+        assert_eq!(message.context.get("c").and_then(Value::as_str), Some("d"));
+        assert!(!message.context.contains_key("context"));
     }
 }
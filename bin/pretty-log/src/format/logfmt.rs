@@ -0,0 +1,474 @@
+use chrono::{DateTime, SecondsFormat};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+
+use super::{text::TextFormat, InputFormat, OutputFormat};
+use crate::message::{Message, Severity};
+use crate::parse::{self, parse, Parse, ParseBuffer, ParseError, Punctuated, QuotedString, Token};
+
+pub struct LogfmtFormat<T: ?Sized = TextFormat> {
+    inner_format: T,
+}
+
+impl<T> LogfmtFormat<T> {
+    pub fn new(inner_format: T) -> LogfmtFormat<T> {
+        LogfmtFormat { inner_format }
+    }
+}
+
+impl<T: InputFormat + ?Sized> InputFormat for LogfmtFormat<T> {
+    fn parse_message(&self, line: &str, default_severity: Severity) -> Option<Message> {
+        let fields = parse_fields(line)?;
+
+        let mut message: Message = Message::from_text("", default_severity);
+        let mut has_text = false;
+
+        for (name, value) in fields {
+            match (name.as_str(), value) {
+                ("time", Some(value)) | ("ts", Some(value)) | ("timestamp", Some(value)) => {
+                    match DateTime::parse_from_rfc3339(&value) {
+                        Ok(time) => message.time = Some(time.into()),
+                        Err(_) => message.add_context(&name, value),
+                    }
+                }
+
+                ("msg", Some(value)) | ("message", Some(value)) => {
+                    message.text = value;
+                    has_text = true;
+                }
+
+                ("level", Some(value)) | ("lvl", Some(value)) | ("at", Some(value)) => {
+                    match Severity::try_parse_str(&value) {
+                        Ok(severity) => message.severity = severity,
+                        Err(_) => message.add_context(&name, value),
+                    }
+                }
+
+                (_, Some(value)) => message.add_context(&name, value),
+                (_, None) => message.add_context(&name, true),
+            }
+        }
+
+        // lines without a message field (such as Heroku router logs) are shown as-is
+        if !has_text {
+            message.text = line.trim().to_string();
+            return Some(message);
+        }
+
+        if let Some(inner_message) = self
+            .inner_format
+            .parse_message(&message.text, Severity::Default)
+        {
+            message.merge_with(inner_message);
+        }
+
+        Some(message)
+    }
+}
+
+impl<T: Send + Sync + ?Sized> OutputFormat for LogfmtFormat<T> {
+    fn print_message<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        message: &Message,
+    ) -> io::Result<()> {
+        let mut fields = Vec::new();
+
+        if let Some(time) = message.time {
+            fields.push((
+                "time".to_string(),
+                time.to_rfc3339_opts(SecondsFormat::AutoSi, false),
+            ));
+        }
+        if message.severity != Severity::Default {
+            fields.push((
+                "level".to_string(),
+                message.severity.to_string().to_lowercase(),
+            ));
+        }
+        fields.push(("msg".to_string(), message.text.clone()));
+        if let Some(repeat) = &message.repeat {
+            fields.push(("repeated".to_string(), repeat.count.to_string()));
+            if let Some(time) = repeat.last_time {
+                fields.push((
+                    "last_time".to_string(),
+                    time.to_rfc3339_opts(SecondsFormat::AutoSi, false),
+                ));
+            }
+        }
+
+        // context keys that would be read back as one of the fields above, or
+        // that clash with another key, are prefixed with underscores
+        let mut names: HashSet<String> = RESERVED_KEYS.iter().map(|key| key.to_string()).collect();
+        let context: BTreeMap<_, _> = message.context.iter().collect();
+        for (name, value) in context {
+            let value = match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            let mut name = format_key(name);
+            while names.contains(&name) {
+                name.insert(0, '_');
+            }
+            names.insert(name.clone());
+            fields.push((name, value));
+        }
+
+        for (index, (name, value)) in fields.iter().enumerate() {
+            if index > 0 {
+                write!(writer, " ")?;
+            }
+            write!(writer, "{}={}", name, format_value(value))?;
+        }
+
+        writeln!(writer)
+    }
+}
+
+/// Keys that are read as the time, message or severity of a message.
+const FIELD_KEYS: [&str; 8] = [
+    "time",
+    "ts",
+    "timestamp",
+    "msg",
+    "message",
+    "level",
+    "lvl",
+    "at",
+];
+
+/// Keys that are read as fields of a message rather than as context.
+const RESERVED_KEYS: [&str; 10] = [
+    "time",
+    "ts",
+    "timestamp",
+    "msg",
+    "message",
+    "level",
+    "lvl",
+    "at",
+    "repeated",
+    "last_time",
+];
+
+/// Replace characters in a key that the `go` format does not accept.
+fn format_key(key: &str) -> String {
+    key.chars()
+        .map(|ch| match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '/' | '_' | '-' => ch,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Write a value bare if possible, and otherwise as a quoted string using only
+/// the escape sequences understood by the `go` format.
+fn format_value(value: &str) -> String {
+    let is_bare = !value.is_empty()
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '/' || ch == '_' || ch == '-');
+    if is_bare {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a logfmt line into a list of fields, where fields written as a bare
+/// key have no value.
+///
+/// Returns `None` if the line does not look like logfmt, that is, unless it has
+/// a time, level or message field, or every field is a `key=value` pair.
+fn parse_fields(line: &str) -> Option<Vec<(String, Option<String>)>> {
+    let fields: Punctuated<Field, Whitespace> = parse(line.trim()).ok()?;
+
+    let fields: Vec<_> = fields
+        .into_items()
+        .map(|field| (field.key.0.to_string(), field.value.map(Value::into_string)))
+        .collect();
+
+    let has_known_key = fields
+        .iter()
+        .any(|(key, value)| value.is_some() && FIELD_KEYS.contains(&key.as_str()));
+    let is_all_pairs = fields.iter().all(|(_, value)| value.is_some());
+    if !has_known_key && !is_all_pairs {
+        return None;
+    }
+
+    Some(fields)
+}
+
+#[derive(Debug)]
+struct Field<'a> {
+    key: Key<'a>,
+    value: Option<Value<'a>>,
+}
+
+impl<'a> Parse<'a> for Field<'a> {
+    fn parse(buf: &mut ParseBuffer<'a>) -> parse::Result<Self> {
+        let key = buf.parse::<Key>()?;
+
+        let value = match buf.parse::<Option<Equals>>()? {
+            Some(_) => Some(buf.parse::<Option<Value>>()?.unwrap_or(Value::Empty)),
+            None => None,
+        };
+
+        Ok(Field { key, value })
+    }
+}
+
+#[derive(Debug)]
+struct Whitespace;
+
+impl<'a> Token<'a> for Whitespace {
+    fn parse_token(s: &'a str) -> parse::Result<(Whitespace, &'a str)> {
+        let rest = s.trim_start();
+        if rest.len() < s.len() {
+            Ok((Whitespace, rest))
+        } else {
+            Err(ParseError::custom("Expected whitespace"))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Equals;
+
+impl<'a> Token<'a> for Equals {
+    fn parse_token(s: &'a str) -> parse::Result<(Equals, &'a str)> {
+        if let Some(rest) = s.strip_prefix('=') {
+            Ok((Equals, rest))
+        } else {
+            Err(ParseError::custom("Expected '='"))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Key<'a>(&'a str);
+
+impl<'a> Token<'a> for Key<'a> {
+    fn parse_token(s: &'a str) -> parse::Result<(Key<'a>, &'a str)> {
+        let end = s
+            .find(|ch: char| ch.is_whitespace() || ch == '=' || ch == '"')
+            .unwrap_or(s.len());
+
+        if end > 0 {
+            Ok((Key(&s[..end]), &s[end..]))
+        } else {
+            Err(ParseError::custom("Invalid key"))
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Value<'a> {
+    Empty,
+    Bare(&'a str),
+    Quoted(String),
+}
+
+impl<'a> Value<'a> {
+    fn into_string(self) -> String {
+        match self {
+            Value::Empty => String::new(),
+            Value::Bare(value) => value.to_string(),
+            Value::Quoted(value) => value,
+        }
+    }
+}
+
+impl<'a> Token<'a> for Value<'a> {
+    fn parse_token(s: &'a str) -> parse::Result<(Value<'a>, &'a str)> {
+        if s.starts_with('"') {
+            let (QuotedString(value), rest) = QuotedString::parse_token(s)?;
+            return Ok((Value::Quoted(value), rest));
+        }
+
+        let end = s.find(char::is_whitespace).unwrap_or(s.len());
+        if end > 0 {
+            Ok((Value::Bare(&s[..end]), &s[end..]))
+        } else {
+            Err(ParseError::custom("Invalid value"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Value;
+
+    fn fields(line: &str) -> Vec<(String, Option<String>)> {
+        parse_fields(line).expect("valid logfmt")
+    }
+
+    fn field(key: &str, value: Option<&str>) -> (String, Option<String>) {
+        (key.to_string(), value.map(str::to_string))
+    }
+
+    #[test]
+    fn parse_keys_and_values() {
+        assert_eq!(
+            fields(r#"at=info  http.method=GET   path=/a?b=c fwd="1.2.3.4" dyno=web.1 cached"#),
+            vec![
+                field("at", Some("info")),
+                field("http.method", Some("GET")),
+                field("path", Some("/a?b=c")),
+                field("fwd", Some("1.2.3.4")),
+                field("dyno", Some("web.1")),
+                field("cached", None),
+            ]
+        );
+
+        assert_eq!(
+            fields("addr=127.0.0.1:8080 empty= msg=ok"),
+            vec![
+                field("addr", Some("127.0.0.1:8080")),
+                field("empty", Some("")),
+                field("msg", Some("ok")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_escapes() {
+        assert_eq!(
+            fields(r#"msg="a \"b\"\n\tc \u00e6 \ud83d\ude00 \q""#),
+            vec![field("msg", Some("a \"b\"\n\tc æ 😀 \\q"))]
+        );
+    }
+
+    #[test]
+    fn reject_plain_text() {
+        assert!(parse_fields("Starting server on port=8080").is_none());
+        assert!(parse_fields("hello world").is_none());
+        assert!(parse_fields("Done key=value").is_none());
+        assert!(parse_fields("retrying with timeout=30s").is_none());
+        assert!(parse_fields("key=value other=1").is_some());
+        assert!(parse_fields(r#"msg="unterminated"#).is_none());
+    }
+
+    #[test]
+    fn format_values() {
+        assert_eq!(format_key("http.method"), "http_method");
+        assert_eq!(format_value("web-1/a_b"), "web-1/a_b");
+        assert_eq!(format_value(""), r#""""#);
+        assert_eq!(format_value("1.5"), r#""1.5""#);
+        assert_eq!(format_value("a \"b\"\n\\"), r#""a \"b\"\n\\""#);
+    }
+
+    #[test]
+    fn round_trip() {
+        let line =
+            r#"time=2020-03-01T12:00:00.5Z level=error msg="disk \"full\"\n" disk=3 path=/a"#;
+        let format = LogfmtFormat::new(TextFormat::new());
+        let message = format.parse_message(line, Severity::Default).unwrap();
+
+        let mut output = Vec::new();
+        format.print_message(&mut output, &message).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let go = super::super::go::GoFormat::new(TextFormat::new());
+        let parsed = go
+            .parse_message(output.trim_end_matches('\n'), Severity::Default)
+            .expect("readable by the go format");
+        assert_eq!(parsed.text, message.text);
+        assert_eq!(parsed.severity, message.severity);
+        assert_eq!(parsed.time, message.time);
+        assert_eq!(parsed.context, message.context);
+    }
+
+    #[test]
+    fn round_trip_severities() {
+        let format = LogfmtFormat::new(TextFormat::new());
+        let go = super::super::go::GoFormat::new(TextFormat::new());
+        let print = |message: &Message| {
+            let mut output = Vec::new();
+            format.print_message(&mut output, message).unwrap();
+            String::from_utf8(output).unwrap().trim_end().to_string()
+        };
+
+        for severity in [
+            Severity::Default,
+            Severity::Debug,
+            Severity::Info,
+            Severity::Notice,
+            Severity::Warning,
+            Severity::Error,
+            Severity::Critical,
+            Severity::Alert,
+            Severity::Emergency,
+        ] {
+            let mut message = Message::from_text("x", severity);
+            let output = print(&message);
+            let parsed = format.parse_message(&output, Severity::Default).unwrap();
+            assert_eq!(parsed.severity, severity, "{}", output);
+
+            message.time = Some(chrono::Local::now());
+            if severity != Severity::Default {
+                let output = print(&message);
+                let parsed = go.parse_message(&output, Severity::Default).unwrap();
+                assert_eq!(parsed.severity, severity, "{}", output);
+            }
+        }
+    }
+
+    #[test]
+    fn colliding_keys() {
+        let format = LogfmtFormat::new(TextFormat::new());
+
+        let mut message = Message::from_text("outer", Severity::Info);
+        message.add_context("msg", "inner");
+        message.add_context("_msg", "underscored");
+        message.add_context("a.b", 1);
+        message.add_context("a_b", 2);
+
+        let mut output = Vec::new();
+        format.print_message(&mut output, &message).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "level=info msg=outer _msg=underscored a_b=1 _a_b=2 __msg=inner\n"
+        );
+
+        let parsed = format
+            .parse_message(output.trim_end(), Severity::Default)
+            .unwrap();
+        assert_eq!(parsed.text, "outer");
+        assert_eq!(parsed.context["__msg"], Value::from("inner"));
+    }
+
+    #[test]
+    fn parse_message() {
+        let format = LogfmtFormat::new(TextFormat::new());
+
+        let message = format
+            .parse_message(
+                r#"time=2020-03-01T12:00:00Z level=warn msg="disk full" disk.id=3"#,
+                Severity::Default,
+            )
+            .unwrap();
+
+        assert_eq!(message.text, "disk full");
+        assert_eq!(message.severity, Severity::Warning);
+        assert!(message.time.is_some());
+        assert_eq!(
+            message.context.get("disk.id").and_then(Value::as_str),
+            Some("3")
+        );
+    }
+}
@@ -1,12 +1,26 @@
+mod container;
+mod context;
+mod embedded;
 mod go;
+mod journald;
 mod json;
+mod logfmt;
 mod pretty;
+mod stats;
 pub mod style;
+mod syslog;
+mod template;
 mod text;
+mod theme;
+mod timestamp;
 
+use std::borrow::Cow;
 use std::io::{self, Write};
 
+pub use self::embedded::json_lines;
+pub use self::json::FieldMapping;
 use self::style::Style;
+pub use self::theme::Theme;
 use crate::message::{Message, Severity};
 use crate::DisplayOptions;
 
@@ -32,7 +46,13 @@ macro_rules! format_select {
                 $(
                     $($pattern)|* => Box::new($expr),
                 )*
-                _ => return Err(format!("Unknown format: {}", format)),
+                _ => {
+                    return Err(format!(
+                        "Unknown format: {} (expected one of {})",
+                        format,
+                        $variants_fn().join(", ")
+                    ))
+                }
             };
             Ok(format)
         }
@@ -50,56 +70,141 @@ macro_rules! format_select {
 }
 
 format_select! {
-    select_fn = get_input_format_impl;
+    select_fn = get_input_format_impl(fields: &FieldMapping, inner: Box<dyn InputFormat>);
     variants_fn = get_input_format_variants;
     default_fn = get_input_format_default;
     type = dyn InputFormat;
-    default = "json,go";
+    default = "json,go,logfmt";
 
-    "json" => json::JsonFormat::new(text::TextFormat::new()),
+    "json" => json::JsonFormat::new(fields, inner),
     "text" => text::TextFormat::new(),
-    "go" => go::GoFormat::new(text::TextFormat::new()),
+    "go" => go::GoFormat::new(inner),
+    "logfmt" => logfmt::LogfmtFormat::new(inner),
+    "docker" => container::DockerFormat::new(inner),
+    "cri" => container::CriFormat::new(inner),
+    "syslog" => syslog::SyslogFormat::new(inner),
+    "journald" => journald::JournaldFormat::new(inner),
 }
 
 format_select! {
-    select_fn = get_output_format(style: impl Style, display_opts: &DisplayOptions);
+    select_fn = get_output_format(style: impl Style, fields: &FieldMapping, display_opts: &DisplayOptions);
     variants_fn = get_output_format_variants;
     default_fn = get_output_format_default;
     type = dyn DynOutputFormat;
     default = "pretty";
 
-    "text" => text::TextFormat::new(),
-    "pretty" => pretty::PrettyFormat::new(style, display_opts),
+    "text" => text::TextFormat::with_options(display_opts)?,
+    "pretty" => pretty::PrettyFormat::new(style, display_opts)?,
+    "json" | "ndjson" => json::JsonFormat::new(fields, text::TextFormat::new()),
+    "logfmt" => logfmt::LogfmtFormat::new(text::TextFormat::new()),
+    "template" => template::TemplateFormat::new(style, display_opts)?,
+    "stats" => stats::StatsFormat::new(style, display_opts)?,
 }
 
-pub fn get_input_format(formats: &[impl AsRef<str>]) -> Result<Box<dyn InputFormat>, String> {
+/// Get the input format to try for each line, from a list of formats to try in
+/// order.
+///
+/// Each format may be a chain such as `json>logfmt`, where the message text
+/// read by the outer format is parsed with the inner format.
+pub fn get_input_format(
+    formats: &[impl AsRef<str>],
+    fields: &FieldMapping,
+) -> Result<Box<dyn InputFormat>, String> {
     if formats.is_empty() {
         Ok(Box::new(text::TextFormat::new()))
     } else if formats.len() == 1 {
-        get_input_format_impl(formats[0].as_ref())
+        get_input_format_chain(formats[0].as_ref(), fields)
     } else {
         let formats = formats
             .iter()
-            .map(|format| get_input_format_impl(format.as_ref()))
+            .map(|format| get_input_format_chain(format.as_ref(), fields))
             .collect::<Result<Vec<_>, _>>()?;
         Ok(Box::new(ListInputFormat(formats)))
     }
 }
 
+fn get_input_format_chain(
+    chain: &str,
+    fields: &FieldMapping,
+) -> Result<Box<dyn InputFormat>, String> {
+    let mut format: Box<dyn InputFormat> = Box::new(text::TextFormat::new());
+    for name in chain.rsplit('>') {
+        format = get_input_format_impl(name.trim(), fields, format)?;
+    }
+
+    Ok(format)
+}
+
 pub trait InputFormat: Send + Sync {
     fn parse_message(&self, message: &str, default_severity: Severity) -> Option<Message>;
+
+    /// Check whether a line is only the first part of a longer line, which
+    /// continues on the next line.
+    fn is_partial(&self, _line: &str) -> bool {
+        false
+    }
+
+    /// Get the stream that a line belongs to, such as `stdout` or `stderr`,
+    /// where partial lines are only continued by lines of the same stream.
+    fn stream(&self, _line: &str) -> Option<String> {
+        None
+    }
+
+    /// Join a partial line with the line that continues it, or return `None`
+    /// if `line` does not continue it.
+    fn join_partial(&self, _partial: &str, _line: &str) -> Option<String> {
+        None
+    }
+
+    /// Get the text that a line wraps, such as the payload of a container log
+    /// line, or `None` if the line is not wrapped.
+    fn payload<'a>(&self, _line: &'a str) -> Option<Cow<'a, str>> {
+        None
+    }
 }
 
 impl<T: InputFormat + ?Sized> InputFormat for &'_ T {
     fn parse_message(&self, message: &str, default_severity: Severity) -> Option<Message> {
         T::parse_message(self, message, default_severity)
     }
+
+    fn is_partial(&self, line: &str) -> bool {
+        T::is_partial(self, line)
+    }
+
+    fn stream(&self, line: &str) -> Option<String> {
+        T::stream(self, line)
+    }
+
+    fn join_partial(&self, partial: &str, line: &str) -> Option<String> {
+        T::join_partial(self, partial, line)
+    }
+
+    fn payload<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        T::payload(self, line)
+    }
 }
 
 impl<T: InputFormat + ?Sized> InputFormat for Box<T> {
     fn parse_message(&self, message: &str, default_severity: Severity) -> Option<Message> {
         T::parse_message(self, message, default_severity)
     }
+
+    fn is_partial(&self, line: &str) -> bool {
+        T::is_partial(self, line)
+    }
+
+    fn stream(&self, line: &str) -> Option<String> {
+        T::stream(self, line)
+    }
+
+    fn join_partial(&self, partial: &str, line: &str) -> Option<String> {
+        T::join_partial(self, partial, line)
+    }
+
+    fn payload<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        T::payload(self, line)
+    }
 }
 
 pub struct ListInputFormat(Vec<Box<dyn InputFormat>>);
@@ -114,11 +219,40 @@ impl InputFormat for ListInputFormat {
 
         None
     }
+
+    fn is_partial(&self, line: &str) -> bool {
+        self.0.iter().any(|format| format.is_partial(line))
+    }
+
+    fn stream(&self, line: &str) -> Option<String> {
+        self.0.iter().find_map(|format| format.stream(line))
+    }
+
+    fn join_partial(&self, partial: &str, line: &str) -> Option<String> {
+        self.0
+            .iter()
+            .find_map(|format| format.join_partial(partial, line))
+    }
+
+    fn payload<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        self.0.iter().find_map(|format| format.payload(line))
+    }
 }
 
 pub trait OutputFormat: Send + Sync {
     fn print_message<W: Write + ?Sized>(&self, writer: &mut W, message: &Message)
         -> io::Result<()>;
+
+    /// Print anything that is due while waiting for input, such as periodic
+    /// reports. This is called regularly until the end of the input.
+    fn tick<W: Write + ?Sized>(&self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Print anything that is only known at the end of the input.
+    fn finish<W: Write + ?Sized>(&self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 impl<T: OutputFormat + ?Sized> OutputFormat for &'_ T {
@@ -129,6 +263,14 @@ impl<T: OutputFormat + ?Sized> OutputFormat for &'_ T {
     ) -> io::Result<()> {
         T::print_message(self, writer, message)
     }
+
+    fn tick<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        T::tick(self, writer)
+    }
+
+    fn finish<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        T::finish(self, writer)
+    }
 }
 
 impl<T: OutputFormat + ?Sized> OutputFormat for Box<T> {
@@ -139,16 +281,34 @@ impl<T: OutputFormat + ?Sized> OutputFormat for Box<T> {
     ) -> io::Result<()> {
         T::print_message(self, writer, message)
     }
+
+    fn tick<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        T::tick(self, writer)
+    }
+
+    fn finish<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        T::finish(self, writer)
+    }
 }
 
 pub trait DynOutputFormat: Send + Sync {
     fn print_message_dyn(&self, writer: &mut dyn Write, message: &Message) -> io::Result<()>;
+    fn tick_dyn(&self, writer: &mut dyn Write) -> io::Result<()>;
+    fn finish_dyn(&self, writer: &mut dyn Write) -> io::Result<()>;
 }
 
 impl<T: OutputFormat + ?Sized> DynOutputFormat for T {
     fn print_message_dyn(&self, writer: &mut dyn Write, message: &Message) -> io::Result<()> {
         T::print_message(self, writer, message)
     }
+
+    fn tick_dyn(&self, writer: &mut dyn Write) -> io::Result<()> {
+        T::tick(self, writer)
+    }
+
+    fn finish_dyn(&self, writer: &mut dyn Write) -> io::Result<()> {
+        T::finish(self, writer)
+    }
 }
 
 impl OutputFormat for dyn DynOutputFormat {
@@ -159,4 +319,73 @@ impl OutputFormat for dyn DynOutputFormat {
     ) -> io::Result<()> {
         self.print_message_dyn(&mut writer, message)
     }
+
+    fn tick<W: Write + ?Sized>(&self, mut writer: &mut W) -> io::Result<()> {
+        self.tick_dyn(&mut writer)
+    }
+
+    fn finish<W: Write + ?Sized>(&self, mut writer: &mut W) -> io::Result<()> {
+        self.finish_dyn(&mut writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn input_format_chain() {
+        let format = get_input_format(&["json>logfmt"], &FieldMapping::default()).unwrap();
+
+        let message = format
+            .parse_message(
+                r#"{"msg": "level=warn msg=\"disk full\" disk=sda", "time": "2020-03-01T12:00:00Z", "pod": "db-0"}"#,
+                Severity::Default,
+            )
+            .unwrap();
+        assert_eq!(message.text, "disk full");
+        assert_eq!(message.severity, Severity::Warning);
+        assert!(message.time.is_some());
+        assert_eq!(message.context["disk"], json!("sda"));
+        assert_eq!(message.context["pod"], json!("db-0"));
+
+        let message = format
+            .parse_message(r#"{"msg": "plain text"}"#, Severity::Default)
+            .unwrap();
+        assert_eq!(message.text, "plain text");
+
+        assert!(get_input_format(&["json>yaml"], &FieldMapping::default()).is_err());
+    }
+
+    #[test]
+    fn default_input_formats() {
+        let formats: Vec<_> = get_input_format_default().split(',').collect();
+        let format = get_input_format(&formats, &FieldMapping::default()).unwrap();
+
+        // logfmt accepts go lines too, so go has to be tried first
+        let line = r#"time="2020-03-01T12:00:00Z" level=warning msg="disk \"sda\" full" free=0"#;
+        let go = go::GoFormat::new(text::TextFormat::new());
+        assert!(go.parse_message(line, Severity::Default).is_some());
+        assert!(
+            formats.iter().position(|&f| f == "go") < formats.iter().position(|&f| f == "logfmt")
+        );
+
+        let message = format.parse_message(line, Severity::Default).unwrap();
+        assert_eq!(message.text, r#"disk "sda" full"#);
+        assert_eq!(message.severity, Severity::Warning);
+        assert!(message.time.is_some());
+        assert_eq!(message.context["free"], json!("0"));
+
+        let line = r#"time="2020-03-01T12:00:00Z" level=info msg="caf\u00e9 opened""#;
+        let message = format.parse_message(line, Severity::Default).unwrap();
+        assert_eq!(message.text, "café opened");
+
+        // other key=value lines are left to logfmt
+        let line = "at=info method=GET path=/ status=200";
+        assert!(go.parse_message(line, Severity::Default).is_none());
+        let message = format.parse_message(line, Severity::Default).unwrap();
+        assert_eq!(message.text, line);
+        assert_eq!(message.severity, Severity::Info);
+    }
 }
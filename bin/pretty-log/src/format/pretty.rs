@@ -1,21 +1,139 @@
 use std::io::{self, Write};
 
-use super::{style::*, OutputFormat};
+use super::context::context_entries;
+use super::embedded::{json_lines, split_json, Block, Line};
+use super::{style::*, timestamp::TimeDisplay, OutputFormat};
 use crate::ext::*;
 use crate::message::Message;
-use crate::DisplayOptions;
+use crate::{ContextLayout, DisplayOptions};
 
 pub struct PrettyFormat {
     style: AnyStyle,
     opts: DisplayOptions,
+    time: TimeDisplay,
 }
 
 impl PrettyFormat {
-    pub fn new<S: Style>(style: S, opts: &DisplayOptions) -> PrettyFormat {
-        PrettyFormat {
+    pub fn new<S: Style>(style: S, opts: &DisplayOptions) -> Result<PrettyFormat, String> {
+        Ok(PrettyFormat {
             style: style.into(),
             opts: opts.clone(),
+            time: TimeDisplay::new(opts)?,
+        })
+    }
+}
+
+impl PrettyFormat {
+    /// Write the message context as a JSON object, with keys in sorted order.
+    fn write_context_json<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        message: &Message,
+    ) -> io::Result<()> {
+        let severity = message.severity;
+        let mut keys: Vec<_> = message.context.keys().collect();
+        keys.sort();
+
+        for (index, key) in keys.into_iter().enumerate() {
+            let value = serde_json::to_string(&message.context[key])?;
+
+            self.style.element(writer, Element::Separator, severity)?;
+            write!(writer, "{}", if index == 0 { "{" } else { "," })?;
+            self.style.reset(writer)?;
+
+            self.style.element(writer, Element::ContextKey, severity)?;
+            write!(writer, "{}", serde_json::to_string(key)?)?;
+            self.style.reset(writer)?;
+
+            self.style.element(writer, Element::Separator, severity)?;
+            write!(writer, ":")?;
+            self.style.reset(writer)?;
+
+            let plain_value = match &message.context[key] {
+                serde_json::Value::String(s) => s.as_str(),
+                _ => value.as_str(),
+            };
+            self.style
+                .context_value(writer, key, plain_value, severity)?;
+            write!(writer, "{}", value)?;
+            self.style.reset(writer)?;
+        }
+
+        self.style.element(writer, Element::Separator, severity)?;
+        write!(writer, "}}")?;
+        self.style.reset(writer)
+    }
+
+    /// Write the message context on the lines after the message text.
+    fn write_context<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        message: &Message,
+        time_width: usize,
+    ) -> io::Result<()> {
+        let severity = message.severity;
+        let write_marker = |writer: &mut W| -> io::Result<()> {
+            if self.opts.compact {
+                return write!(writer, "  ");
+            }
+
+            self.style.element(writer, Element::Separator, severity)?;
+            write!(writer, "{:width$}+ ", "", width = time_width + LEVEL_WIDTH)?;
+            self.style.reset(writer)
+        };
+
+        if self.opts.context_layout == ContextLayout::Json {
+            write_marker(writer)?;
+            self.write_context_json(writer, message)?;
+            return writeln!(writer);
+        }
+
+        let entries = context_entries(message, &self.opts);
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let key_width = entries
+            .iter()
+            .map(|entry| entry.key.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        for (index, entry) in entries.iter().enumerate() {
+            match self.opts.context_layout {
+                ContextLayout::Inline if index > 0 => write!(writer, " ")?,
+                ContextLayout::Inline => write_marker(writer)?,
+                _ => write_marker(writer)?,
+            }
+
+            self.style.element(writer, Element::ContextKey, severity)?;
+            write!(writer, "{}", entry.key)?;
+            self.style.reset(writer)?;
+
+            self.style.element(writer, Element::Separator, severity)?;
+            if self.opts.context_layout == ContextLayout::Inline {
+                write!(writer, "=")?;
+            } else {
+                let padding = key_width - entry.key.chars().count();
+                write!(writer, "{:padding$} = ", "", padding = padding)?;
+            }
+            self.style.reset(writer)?;
+
+            self.style
+                .context_value(writer, &entry.key, &entry.value, severity)?;
+            write!(writer, "{}", entry.display)?;
+            self.style.reset(writer)?;
+
+            if self.opts.context_layout != ContextLayout::Inline {
+                writeln!(writer)?;
+            }
+        }
+
+        if self.opts.context_layout == ContextLayout::Inline {
+            writeln!(writer)?;
         }
+
+        Ok(())
     }
 }
 
@@ -25,65 +143,171 @@ impl OutputFormat for PrettyFormat {
         writer: &mut W,
         message: &Message,
     ) -> io::Result<()> {
-        let lines: Vec<_> = message.text.split('\n').map(str::to_string).collect();
-        let mut extras = Vec::<String>::new();
+        let severity = message.severity;
+        let time_width = self.time.width();
+        let timestamp = message.time.map(|time| self.time.format(time));
 
-        if self.opts.show_context && !message.context.is_empty() {
-            extras.push(format!("{:?}", message.context))
+        let mut indent = message
+            .source
+            .as_ref()
+            .map_or(0, |source| source.chars().count() + 3);
+        if !self.opts.compact {
+            indent += time_width + LEVEL_WIDTH + 2;
         }
+        let text_width = self
+            .opts
+            .width
+            .and_then(|width| width.checked_sub(indent))
+            .filter(|&width| width >= MIN_TEXT_WIDTH);
+
+        // each row is a line of output, which may be a wrapped part of a
+        // longer line of text
+        let mut rows: Vec<(bool, Line)> = Vec::new();
+        let push_text = |rows: &mut Vec<(bool, Line)>, text: &str| {
+            for line in text.split('\n') {
+                let segments = match text_width {
+                    Some(width) => wrap(line, width),
+                    None => vec![line],
+                };
+                for (segment_no, segment) in segments.into_iter().enumerate() {
+                    rows.push((
+                        segment_no > 0,
+                        vec![(Element::Message, segment.to_string())],
+                    ));
+                }
+            }
+        };
 
-        for (lineno, line) in lines.into_iter().enumerate() {
-            let is_first = lineno == 0;
+        if self.opts.expand_json {
+            for block in split_json(&message.text) {
+                match block {
+                    Block::Text(text) => push_text(&mut rows, text),
+                    Block::Json(value) => {
+                        rows.extend(json_lines(&value).into_iter().map(|line| (false, line)))
+                    }
+                }
+            }
+        } else {
+            push_text(&mut rows, &message.text);
+        }
+
+        if let (Some(repeat), Some((_, spans))) = (&message.repeat, rows.first_mut()) {
+            let summary = match repeat.last_time {
+                Some(time) => format!(
+                    " (×{} until {})",
+                    repeat.count,
+                    self.time.format_absolute(time)
+                ),
+                None => format!(" (×{})", repeat.count),
+            };
+            spans.push((Element::Separator, summary));
+        }
+
+        for (row_no, (is_wrapped, spans)) in rows.into_iter().enumerate() {
+            let is_first = row_no == 0;
 
             if !self.opts.compact {
                 // print prefix fields
-                self.style.severity(writer, message.severity)?;
-                self.style.weak(writer)?;
+                self.style.element(writer, Element::Timestamp, severity)?;
+                match timestamp.as_ref().and_if(|| is_first) {
+                    Some(timestamp) => write!(writer, "{:width$}", timestamp, width = time_width)?,
+                    None => write!(writer, "{:width$}", "", width = time_width)?,
+                }
+                self.style.reset(writer)?;
 
-                if let Some(timestamp) = message.time.as_ref().and_if(|| is_first) {
-                    print!("{}", timestamp.format("%Y-%m-%dT%H:%M:%S%.3f%:z"));
+                self.style.element(writer, Element::Level, severity)?;
+                if is_first {
+                    write!(writer, " {:>9}", severity.to_string().to_uppercase())?;
                 } else {
-                    print!("{:29}", "");
+                    write!(writer, " {:>9}", "")?;
                 }
+                self.style.reset(writer)?;
 
+                self.style.element(writer, Element::Separator, severity)?;
                 if is_first {
-                    print!(" {:>9}", message.severity.to_string().to_uppercase());
+                    write!(writer, "> ")?;
+                } else if is_wrapped {
+                    write!(writer, "  ")?;
                 } else {
-                    print!(" {:>9}", "");
+                    write!(writer, "… ")?;
                 }
+                self.style.reset(writer)?;
+            }
 
+            if let Some(source) = &message.source {
+                self.style.source(writer, source)?;
                 if is_first {
-                    print!("> ");
+                    write!(writer, "[{}] ", source)?;
                 } else {
-                    print!("… ");
+                    write!(writer, "{:width$}", "", width = source.chars().count() + 3)?;
                 }
                 self.style.reset(writer)?;
             }
 
-            self.style.severity(writer, message.severity)?;
-            self.style.strong(writer)?;
-            write!(writer, "{}", line)?;
-
-            self.style.reset(writer)?;
+            for (element, text) in spans {
+                self.style.element(writer, element, severity)?;
+                write!(writer, "{}", text)?;
+                self.style.reset(writer)?;
+            }
 
             writeln!(writer)?;
         }
 
-        for extra in extras.into_iter() {
-            self.style.severity(writer, message.severity)?;
-            self.style.weak(writer)?;
+        if self.opts.show_context && !message.context.is_empty() {
+            self.write_context(writer, message, time_width)?;
+        }
 
-            if !self.opts.compact {
-                write!(writer, "{:39}+ {}", "", extra)?;
-            } else {
-                write!(writer, "{}", extra)?;
-            }
+        Ok(())
+    }
+}
 
-            self.style.reset(writer)?;
+/// The width of the severity level column, including the space before it.
+const LEVEL_WIDTH: usize = 10;
 
-            writeln!(writer)?;
+/// The narrowest column that message text is wrapped to.
+const MIN_TEXT_WIDTH: usize = 20;
+
+/// Split a line into segments of at most `width` characters, breaking at
+/// whitespace where possible.
+fn wrap(line: &str, width: usize) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut rest = line;
+
+    while rest.chars().count() > width {
+        // allow breaking at whitespace right after the first `width` characters
+        let (end, next) = rest.char_indices().nth(width).unwrap();
+        let breaks = &rest[..end + next.len_utf8()];
+
+        match breaks.rfind(char::is_whitespace).filter(|&index| index > 0) {
+            Some(index) => {
+                segments.push(&rest[..index]);
+                rest = rest[index..].trim_start_matches(char::is_whitespace);
+            }
+            None => {
+                segments.push(&rest[..end]);
+                rest = &rest[end..];
+            }
         }
+    }
 
-        Ok(())
+    if !rest.is_empty() || segments.is_empty() {
+        segments.push(rest);
+    }
+    segments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wrap_lines() {
+        assert_eq!(wrap("short", 10), vec!["short"]);
+        assert_eq!(
+            wrap("the quick brown fox jumps", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(wrap("abcdefghijkl", 5), vec!["abcde", "fghij", "kl"]);
+        assert_eq!(wrap("ææææææ", 4), vec!["ææææ", "ææ"]);
     }
 }
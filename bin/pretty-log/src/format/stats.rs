@@ -0,0 +1,632 @@
+use chrono::{Local, TimeZone};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::{style::*, timestamp::TimeDisplay, OutputFormat};
+use crate::collapse::Normalizer;
+use crate::message::{Message, Severity, Value};
+use crate::{DisplayOptions, StatsLayout};
+
+/// Bucket sizes to choose from for the histogram, in seconds.
+const BUCKET_SIZES: [i64; 14] = [
+    1, 5, 10, 30, 60, 300, 600, 1800, 3600, 10800, 21600, 43200, 86400, 604800,
+];
+
+/// The largest number of buckets when choosing a bucket size.
+const MAX_BUCKETS: i64 = 30;
+
+const BAR_WIDTH: usize = 40;
+
+/// The widest label shown in a table, such as a message text.
+const MAX_LABEL_WIDTH: usize = 60;
+
+/// The most distinct message texts, or values of a key, that are counted,
+/// after which further ones are counted together under `OTHER`.
+const MAX_DISTINCT: usize = 10000;
+
+const OTHER: &str = "<other>";
+
+/// The most entries in the per-second counts before they are merged into
+/// coarser ones.
+const MAX_SECONDS: usize = 100000;
+
+/// Prints a summary of the messages, rather than the messages themselves, at
+/// the end of the input and (if `--stats-interval` is given) while reading.
+pub struct StatsFormat {
+    style: AnyStyle,
+    opts: DisplayOptions,
+    time: TimeDisplay,
+    stats: Mutex<Stats>,
+}
+
+struct Stats {
+    total: usize,
+    severities: BTreeMap<Severity, usize>,
+
+    /// Message counts per second since the epoch, rounded down to a multiple
+    /// of `resolution`.
+    seconds: BTreeMap<i64, usize>,
+
+    /// The number of seconds counted together in `seconds`, which is one of
+    /// the `BUCKET_SIZES` that is never larger than the histogram's bucket.
+    resolution: i64,
+
+    /// Counts of the values of each of the `--stats-keys`.
+    values: HashMap<String, HashMap<String, usize>>,
+
+    /// Counts of normalized message texts.
+    texts: HashMap<String, usize>,
+
+    last_report: Instant,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    total: usize,
+    severities: Vec<Count>,
+    histogram: Histogram,
+    values: BTreeMap<String, Vec<Count>>,
+    messages: Vec<Count>,
+}
+
+#[derive(Debug, Serialize)]
+struct Histogram {
+    bucket_seconds: i64,
+    buckets: Vec<Count>,
+}
+
+#[derive(Debug, Serialize)]
+struct Count {
+    value: String,
+    count: usize,
+}
+
+impl StatsFormat {
+    pub fn new<S: Style>(style: S, opts: &DisplayOptions) -> Result<StatsFormat, String> {
+        Ok(StatsFormat {
+            style: style.into(),
+            opts: opts.clone(),
+            time: TimeDisplay::new(opts)?,
+            stats: Mutex::new(Stats {
+                total: 0,
+                severities: BTreeMap::new(),
+                seconds: BTreeMap::new(),
+                resolution: 1,
+                values: HashMap::new(),
+                texts: HashMap::new(),
+                last_report: Instant::now(),
+            }),
+        })
+    }
+
+    fn record(&self, stats: &mut Stats, message: &Message) {
+        // a collapsed message counts for each of its repeats
+        let count = message.repeat.as_ref().map_or(1, |repeat| repeat.count);
+
+        stats.total += count;
+        *stats.severities.entry(message.severity).or_default() += count;
+
+        if let Some(time) = message.time {
+            let second = time.timestamp().div_euclid(stats.resolution) * stats.resolution;
+            *stats.seconds.entry(second).or_default() += count;
+            if stats.seconds.len() > MAX_SECONDS {
+                stats.coarsen();
+            }
+        }
+
+        for key in &self.opts.stats_keys {
+            let value = match message.lookup(key) {
+                None | Some(Value::Null) => continue,
+                Some(Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+            };
+            let values = stats.values.entry(key.clone()).or_default();
+            add_count(values, value, count);
+        }
+
+        add_count(&mut stats.texts, normalize(&message.text), count);
+    }
+
+    fn report(&self, stats: &Stats) -> Report {
+        let top = |counts: &HashMap<String, usize>| {
+            let mut counts: Vec<_> = counts.iter().collect();
+            counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+            counts
+                .into_iter()
+                .take(self.opts.stats_top)
+                .map(|(value, &count)| Count {
+                    value: value.clone(),
+                    count,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        Report {
+            total: stats.total,
+            severities: stats
+                .severities
+                .iter()
+                .rev()
+                .map(|(severity, &count)| Count {
+                    value: severity.to_string().to_lowercase(),
+                    count,
+                })
+                .collect(),
+            histogram: self.histogram(stats),
+            values: self
+                .opts
+                .stats_keys
+                .iter()
+                .map(|key| {
+                    (
+                        key.clone(),
+                        stats.values.get(key).map(top).unwrap_or_default(),
+                    )
+                })
+                .collect(),
+            messages: top(&stats.texts),
+        }
+    }
+
+    fn histogram(&self, stats: &Stats) -> Histogram {
+        let (first, last) = match (stats.seconds.keys().next(), stats.seconds.keys().last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => {
+                return Histogram {
+                    bucket_seconds: 0,
+                    buckets: Vec::new(),
+                }
+            }
+        };
+
+        // the requested bucket size (if any) is grown to the next size that
+        // gives few enough buckets, and beyond the largest size to whole weeks
+        let requested = self
+            .opts
+            .stats_bucket
+            .map(|bucket| (bucket.as_secs() as i64).max(1));
+        let largest = BUCKET_SIZES[BUCKET_SIZES.len() - 1];
+        let size = requested
+            .into_iter()
+            .chain(
+                BUCKET_SIZES
+                    .iter()
+                    .copied()
+                    .filter(|&size| requested.is_none_or(|requested| size > requested)),
+            )
+            .find(|size| (last - first) / size < MAX_BUCKETS)
+            .unwrap_or_else(|| ((last - first) / MAX_BUCKETS / largest + 1) * largest);
+
+        let format = if size < 60 {
+            "%Y-%m-%d %H:%M:%S"
+        } else if size < 86400 {
+            "%Y-%m-%d %H:%M"
+        } else {
+            "%Y-%m-%d"
+        };
+
+        let mut counts = BTreeMap::new();
+        for (&second, &count) in &stats.seconds {
+            *counts.entry(second.div_euclid(size)).or_default() += count;
+        }
+
+        let buckets = (first.div_euclid(size)..=last.div_euclid(size))
+            .map(|bucket| Count {
+                value: Local
+                    .timestamp_opt(bucket * size, 0)
+                    .single()
+                    .map(|time| self.time.format_with(time, format))
+                    .unwrap_or_default(),
+                count: counts.get(&bucket).copied().unwrap_or(0),
+            })
+            .collect();
+
+        Histogram {
+            bucket_seconds: size,
+            buckets,
+        }
+    }
+
+    fn print_report<W: Write + ?Sized>(&self, writer: &mut W, report: &Report) -> io::Result<()> {
+        if self.opts.stats_layout == StatsLayout::Json {
+            serde_json::to_writer(&mut *writer, report)?;
+            return writeln!(writer);
+        }
+
+        self.style
+            .element(writer, Element::Message, Severity::Default)?;
+        write!(writer, "{} messages", report.total)?;
+        self.style.reset(writer)?;
+        writeln!(writer)?;
+
+        let severities: Vec<_> = report
+            .severities
+            .iter()
+            .map(|count| {
+                let severity = Severity::try_parse_str(&count.value).unwrap_or_default();
+                (count, severity)
+            })
+            .collect();
+        self.print_table(writer, "Severity", &severities)?;
+
+        if !report.histogram.buckets.is_empty() {
+            let title = format!(
+                "Time (per {})",
+                format_seconds(report.histogram.bucket_seconds)
+            );
+            let buckets: Vec<_> = report
+                .histogram
+                .buckets
+                .iter()
+                .map(|count| (count, Severity::Default))
+                .collect();
+            self.print_table(writer, &title, &buckets)?;
+        }
+
+        for (key, values) in &report.values {
+            let values: Vec<_> = values
+                .iter()
+                .map(|count| (count, Severity::Default))
+                .collect();
+            self.print_table(writer, key, &values)?;
+        }
+
+        let messages: Vec<_> = report
+            .messages
+            .iter()
+            .map(|count| (count, Severity::Default))
+            .collect();
+        self.print_table(writer, "Message", &messages)
+    }
+
+    /// Print a table of counts with a bar for each, where the label of each row
+    /// is styled by the given severity.
+    fn print_table<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        title: &str,
+        rows: &[(&Count, Severity)],
+    ) -> io::Result<()> {
+        let labels: Vec<String> = rows
+            .iter()
+            .map(|(count, _)| truncate(&count.value, MAX_LABEL_WIDTH))
+            .collect();
+        let label_width = labels
+            .iter()
+            .map(|label| label.chars().count())
+            .chain(Some(title.chars().count()))
+            .max()
+            .unwrap_or(0);
+        let max = rows.iter().map(|(count, _)| count.count).max().unwrap_or(0);
+        let count_width = max.to_string().len().max("Count".len());
+
+        writeln!(writer)?;
+        self.style
+            .element(writer, Element::Message, Severity::Default)?;
+        write!(
+            writer,
+            "{:label_width$}  {:>count_width$}",
+            title,
+            "Count",
+            label_width = label_width,
+            count_width = count_width
+        )?;
+        self.style.reset(writer)?;
+        writeln!(writer)?;
+
+        for ((count, severity), label) in rows.iter().zip(labels) {
+            let element = if *severity == Severity::Default {
+                Element::ContextValue
+            } else {
+                Element::Severity
+            };
+            self.style.element(writer, element, *severity)?;
+            write!(writer, "{:label_width$}", label, label_width = label_width)?;
+            self.style.reset(writer)?;
+
+            let bar = (count.count * BAR_WIDTH).div_ceil(max.max(1));
+            write!(
+                writer,
+                "  {:>count_width$}  ",
+                count.count,
+                count_width = count_width
+            )?;
+            self.style.element(writer, Element::Severity, *severity)?;
+            write!(writer, "{}", "█".repeat(bar))?;
+            self.style.reset(writer)?;
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_report<W: Write + ?Sized>(&self, writer: &mut W, stats: &mut Stats) -> io::Result<()> {
+        // redraw periodic reports in place on a terminal
+        if self.opts.stats_interval.is_some()
+            && self.opts.stats_layout == StatsLayout::Table
+            && self.opts.redraw
+        {
+            write!(writer, "\x1b[H\x1b[2J")?;
+        }
+
+        stats.last_report = Instant::now();
+        self.print_report(writer, &self.report(stats))?;
+        writer.flush()
+    }
+}
+
+impl Stats {
+    /// Merge the per-second counts into the largest of the `BUCKET_SIZES` that
+    /// still gives at least `MAX_BUCKETS` over their time range. The histogram
+    /// never chooses a smaller bucket, and each size divides the larger ones,
+    /// so the merged counts still fall into the same buckets.
+    fn coarsen(&mut self) {
+        let range = match (self.seconds.keys().next(), self.seconds.keys().last()) {
+            (Some(&first), Some(&last)) => last - first,
+            _ => return,
+        };
+        let resolution = BUCKET_SIZES
+            .iter()
+            .copied()
+            .take_while(|&size| range / size >= MAX_BUCKETS)
+            .last()
+            .unwrap_or(1);
+        if resolution <= self.resolution {
+            return;
+        }
+
+        let mut seconds = BTreeMap::new();
+        for (second, count) in std::mem::take(&mut self.seconds) {
+            *seconds
+                .entry(second.div_euclid(resolution) * resolution)
+                .or_default() += count;
+        }
+        self.seconds = seconds;
+        self.resolution = resolution;
+    }
+}
+
+/// Count a value, unless there are too many distinct values already, in which
+/// case count it under `OTHER`.
+fn add_count(counts: &mut HashMap<String, usize>, value: String, count: usize) {
+    let value = if counts.len() >= MAX_DISTINCT && !counts.contains_key(&value) {
+        OTHER.to_string()
+    } else {
+        value
+    };
+    *counts.entry(value).or_default() += count;
+}
+
+impl OutputFormat for StatsFormat {
+    fn print_message<W: Write + ?Sized>(
+        &self,
+        _writer: &mut W,
+        message: &Message,
+    ) -> io::Result<()> {
+        let mut stats = self.stats.lock().unwrap();
+        self.record(&mut stats, message);
+        Ok(())
+    }
+
+    fn tick<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let mut stats = self.stats.lock().unwrap();
+        match self.opts.stats_interval {
+            Some(interval) if stats.last_report.elapsed() >= interval => {
+                self.write_report(writer, &mut stats)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn finish<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let mut stats = self.stats.lock().unwrap();
+        self.write_report(writer, &mut stats)
+    }
+}
+
+/// Reduce a message to its first line, with numbers and IDs masked, so that
+/// messages that only differ in those are counted together.
+fn normalize(text: &str) -> String {
+    lazy_static! {
+        static ref NORMALIZER: Normalizer = Normalizer::default();
+    }
+
+    NORMALIZER.normalize(text.lines().next().unwrap_or("").trim())
+}
+
+fn truncate(value: &str, max_width: usize) -> String {
+    if value.chars().count() > max_width {
+        let mut value: String = value.chars().take(max_width - 1).collect();
+        value.push('…');
+        value
+    } else {
+        value.to_string()
+    }
+}
+
+/// Format a number of seconds with the largest unit that divides it.
+fn format_seconds(seconds: i64) -> String {
+    for (unit, size) in &[("w", 604800), ("d", 86400), ("h", 3600), ("m", 60)] {
+        if seconds % size == 0 {
+            return format!("{}{}", seconds / size, unit);
+        }
+    }
+    format!("{}s", seconds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Repeat;
+    use chrono::DateTime;
+    use serde_json::json;
+    use structopt::StructOpt;
+
+    #[test]
+    fn normalize_texts() {
+        assert_eq!(
+            normalize("user 42 logged in from 10.0.0.1 after 1.5s\nat main.rs:10"),
+            "user <n> logged in from <n> after <n>s"
+        );
+        assert_eq!(
+            normalize(
+                "request 3f2a9c1e-0b7d-4c1a-9e3f-5a6b7c8d9e0f failed at 0x7ffd, tx deadbeef12"
+            ),
+            "request <id> failed at <id>, tx <id>"
+        );
+        assert_eq!(normalize("cache facade rebuilt"), "cache facade rebuilt");
+        assert_eq!(format_seconds(300), "5m");
+        assert_eq!(format_seconds(90), "90s");
+    }
+
+    #[test]
+    fn json_report() {
+        let args = [
+            "test",
+            "--utc",
+            "--stats-layout",
+            "json",
+            "--stats-keys",
+            "service",
+            "--stats-top",
+            "2",
+        ];
+        let format = StatsFormat::new(PlainStyle, &DisplayOptions::from_iter(&args)).unwrap();
+
+        let start = DateTime::parse_from_rfc3339("2020-03-01T12:00:00Z").unwrap();
+        let mut output = Vec::new();
+        for (seconds, severity, service, text) in [
+            (0, Severity::Info, "api", "request 1 done"),
+            (30, Severity::Info, "api", "request 2 done"),
+            (90, Severity::Error, "db", "connection lost"),
+            (150, Severity::Info, "web", "request 3 done"),
+        ] {
+            let mut message = Message::from_text(text, severity);
+            message.time = Some((start + chrono::Duration::seconds(seconds)).into());
+            message.add_context("service", service);
+            format.print_message(&mut output, &message).unwrap();
+        }
+        assert!(output.is_empty());
+
+        format.finish(&mut output).unwrap();
+        let report: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            report,
+            json!({
+                "total": 4,
+                "severities": [
+                    { "value": "error", "count": 1 },
+                    { "value": "info", "count": 3 },
+                ],
+                "histogram": {
+                    "bucket_seconds": 10,
+                    "buckets": (0..16)
+                        .map(|index| {
+                            let count = match index { 0 | 3 | 9 | 15 => 1, _ => 0 };
+                            json!({
+                                "value": format!("2020-03-01 12:{:02}:{}0", index / 6, index % 6),
+                                "count": count,
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                },
+                "values": {
+                    "service": [
+                        { "value": "api", "count": 2 },
+                        { "value": "db", "count": 1 },
+                    ],
+                },
+                "messages": [
+                    { "value": "request <n> done", "count": 3 },
+                    { "value": "connection lost", "count": 1 },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn bounded_histogram() {
+        let args = [
+            "test",
+            "--utc",
+            "--stats-bucket",
+            "1s",
+            "--stats-interval",
+            "0s",
+        ];
+        let format = StatsFormat::new(PlainStyle, &DisplayOptions::from_iter(&args)).unwrap();
+
+        let mut output = Vec::new();
+        for time in ["2000-01-01T00:00:00Z", "2020-01-01T00:00:00Z"] {
+            let mut message = Message::from_text("tick", Severity::Info);
+            message.time = Some(DateTime::parse_from_rfc3339(time).unwrap().into());
+            format.print_message(&mut output, &message).unwrap();
+        }
+        assert!(output.is_empty());
+
+        let stats = format.stats.lock().unwrap();
+        let histogram = format.histogram(&stats);
+        assert_eq!(histogram.bucket_seconds % 604800, 0);
+        assert!(histogram.buckets.len() <= MAX_BUCKETS as usize + 1);
+        assert_eq!(histogram.buckets.first().unwrap().count, 1);
+        assert_eq!(histogram.buckets.last().unwrap().count, 1);
+        drop(stats);
+
+        // periodic reports are written when ticked, without further messages
+        format.tick(&mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("2 messages"));
+    }
+
+    #[test]
+    fn collapsed_repeats() {
+        let args = ["test", "--stats-layout", "json"];
+        let format = StatsFormat::new(PlainStyle, &DisplayOptions::from_iter(&args)).unwrap();
+
+        let mut output = Vec::new();
+        let mut message = Message::from_text("x 1", Severity::Default);
+        message.repeat = Some(Repeat {
+            count: 3,
+            last_time: None,
+        });
+        format.print_message(&mut output, &message).unwrap();
+        let message = Message::from_text("y", Severity::Default);
+        format.print_message(&mut output, &message).unwrap();
+
+        format.finish(&mut output).unwrap();
+        let report: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(report["total"], json!(4));
+        assert_eq!(
+            report["messages"],
+            json!([{ "value": "x <n>", "count": 3 }, { "value": "y", "count": 1 }])
+        );
+    }
+
+    #[test]
+    fn bounded_counts() {
+        let mut counts = HashMap::new();
+        for index in 0..MAX_DISTINCT + 5 {
+            add_count(&mut counts, format!("text {}", index), 1);
+        }
+        add_count(&mut counts, "text 0".into(), 2);
+        assert_eq!(counts.len(), MAX_DISTINCT + 1);
+        assert_eq!(counts["text 0"], 3);
+        assert_eq!(counts[OTHER], 5);
+
+        let args = ["test", "--utc", "--stats-layout", "json"];
+        let format = StatsFormat::new(PlainStyle, &DisplayOptions::from_iter(&args)).unwrap();
+        let mut stats = format.stats.lock().unwrap();
+        stats.seconds = (0..MAX_SECONDS as i64 + 1)
+            .map(|second| (second * 7, 1))
+            .collect();
+        let before = format!("{:?}", format.histogram(&stats));
+        stats.coarsen();
+        assert_eq!(stats.resolution, 21600);
+        assert!(stats.seconds.len() < MAX_SECONDS / 100);
+        assert_eq!(stats.seconds.values().sum::<usize>(), MAX_SECONDS + 1);
+        assert_eq!(format!("{:?}", format.histogram(&stats)), before);
+    }
+}
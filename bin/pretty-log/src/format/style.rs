@@ -1,12 +1,38 @@
 use std::io::{self, Write};
 
+use super::theme::{TextStyle, Theme};
 use crate::message::Severity;
 
+/// A part of a message that can be styled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Element {
+    /// The style of the message severity alone.
+    Severity,
+    Timestamp,
+    Level,
+    Separator,
+    Message,
+    ContextKey,
+    ContextValue,
+}
+
 pub trait Style: Into<AnyStyle> {
     fn reset<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()>;
-    fn severity<W: Write + ?Sized>(&self, writer: &mut W, severity: Severity) -> io::Result<()>;
-    fn weak<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()>;
-    fn strong<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()>;
+    fn element<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        element: Element,
+        severity: Severity,
+    ) -> io::Result<()>;
+    fn context_value<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        key: &str,
+        value: &str,
+        severity: Severity,
+    ) -> io::Result<()>;
+    fn source<W: Write + ?Sized>(&self, writer: &mut W, source: &str) -> io::Result<()>;
+    fn text_style<W: Write + ?Sized>(&self, writer: &mut W, style: &TextStyle) -> io::Result<()>;
 }
 
 pub enum AnyStyle {
@@ -22,24 +48,46 @@ impl Style for AnyStyle {
         }
     }
 
-    fn severity<W: Write + ?Sized>(&self, writer: &mut W, severity: Severity) -> io::Result<()> {
+    fn element<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        element: Element,
+        severity: Severity,
+    ) -> io::Result<()> {
         match self {
-            AnyStyle::Plain(style) => style.severity(writer, severity),
-            AnyStyle::Colored(style) => style.severity(writer, severity),
+            AnyStyle::Plain(style) => style.element(writer, element, severity),
+            AnyStyle::Colored(style) => style.element(writer, element, severity),
         }
     }
 
-    fn weak<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+    fn context_value<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        key: &str,
+        value: &str,
+        severity: Severity,
+    ) -> io::Result<()> {
         match self {
-            AnyStyle::Plain(style) => style.weak(writer),
-            AnyStyle::Colored(style) => style.weak(writer),
+            AnyStyle::Plain(style) => style.context_value(writer, key, value, severity),
+            AnyStyle::Colored(style) => style.context_value(writer, key, value, severity),
         }
     }
 
-    fn strong<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+    fn source<W: Write + ?Sized>(&self, writer: &mut W, source: &str) -> io::Result<()> {
         match self {
-            AnyStyle::Plain(style) => style.strong(writer),
-            AnyStyle::Colored(style) => style.strong(writer),
+            AnyStyle::Plain(style) => style.source(writer, source),
+            AnyStyle::Colored(style) => style.source(writer, source),
+        }
+    }
+
+    fn text_style<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        text_style: &TextStyle,
+    ) -> io::Result<()> {
+        match self {
+            AnyStyle::Plain(style) => style.text_style(writer, text_style),
+            AnyStyle::Colored(style) => style.text_style(writer, text_style),
         }
     }
 }
@@ -57,20 +105,46 @@ impl Style for PlainStyle {
         Ok(())
     }
 
-    fn severity<W: Write + ?Sized>(&self, _writer: &mut W, _severity: Severity) -> io::Result<()> {
+    fn element<W: Write + ?Sized>(
+        &self,
+        _writer: &mut W,
+        _element: Element,
+        _severity: Severity,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn context_value<W: Write + ?Sized>(
+        &self,
+        _writer: &mut W,
+        _key: &str,
+        _value: &str,
+        _severity: Severity,
+    ) -> io::Result<()> {
         Ok(())
     }
 
-    fn weak<W: Write + ?Sized>(&self, _writer: &mut W) -> io::Result<()> {
+    fn source<W: Write + ?Sized>(&self, _writer: &mut W, _source: &str) -> io::Result<()> {
         Ok(())
     }
 
-    fn strong<W: Write + ?Sized>(&self, _writer: &mut W) -> io::Result<()> {
+    fn text_style<W: Write + ?Sized>(&self, _writer: &mut W, _style: &TextStyle) -> io::Result<()> {
         Ok(())
     }
 }
 
-pub struct ColoredStyle;
+/// ANSI colours and attributes, as given by a theme.
+pub struct ColoredStyle {
+    theme: Box<Theme>,
+}
+
+impl ColoredStyle {
+    pub fn new(theme: Theme) -> ColoredStyle {
+        ColoredStyle {
+            theme: Box::new(theme),
+        }
+    }
+}
 
 impl From<ColoredStyle> for AnyStyle {
     fn from(style: ColoredStyle) -> AnyStyle {
@@ -83,25 +157,47 @@ impl Style for ColoredStyle {
         write!(writer, "\x1b[0m")
     }
 
-    fn severity<W: Write + ?Sized>(&self, writer: &mut W, severity: Severity) -> io::Result<()> {
-        if severity >= Severity::Error {
-            write!(writer, "\x1b[31m")
-        } else if severity >= Severity::Warning {
-            write!(writer, "\x1b[33m")
-        } else if severity >= Severity::Info {
-            write!(writer, "\x1b[34m")
-        } else if severity >= Severity::Debug {
-            write!(writer, "\x1b[32m")
-        } else {
-            Ok(())
+    fn element<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        element: Element,
+        severity: Severity,
+    ) -> io::Result<()> {
+        let theme = &self.theme;
+        let style = match element {
+            Element::Severity => return self.text_style(writer, theme.severity(severity)),
+            Element::Timestamp => &theme.timestamp,
+            Element::Level => &theme.level,
+            Element::Separator => &theme.separator,
+            Element::Message => &theme.message,
+            Element::ContextKey => &theme.context_key,
+            Element::ContextValue => &theme.context_value,
+        };
+
+        self.text_style(writer, &theme.severity(severity).with(style))
+    }
+
+    fn context_value<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        key: &str,
+        value: &str,
+        severity: Severity,
+    ) -> io::Result<()> {
+        match self.theme.highlight(key, value) {
+            Some(style) => self.text_style(writer, &style),
+            None => self.element(writer, Element::ContextValue, severity),
         }
     }
 
-    fn weak<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
-        write!(writer, "\x1b[2m")
+    fn source<W: Write + ?Sized>(&self, writer: &mut W, source: &str) -> io::Result<()> {
+        self.text_style(writer, &self.theme.hashed(source))
     }
 
-    fn strong<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
-        write!(writer, "\x1b[1m")
+    fn text_style<W: Write + ?Sized>(&self, writer: &mut W, style: &TextStyle) -> io::Result<()> {
+        match style.to_sgr() {
+            Some(sgr) => write!(writer, "{}", sgr),
+            None => Ok(()),
+        }
     }
 }
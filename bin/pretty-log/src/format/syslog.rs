@@ -0,0 +1,373 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Map;
+
+use super::{text::TextFormat, InputFormat};
+use crate::message::{Message, Severity, Value};
+use crate::parse::{self, regex_token, ParseError, Token};
+
+/// Syslog lines in the formats of RFC 5424, such as
+/// `<165>1 2020-03-01T12:00:00.003Z host app 1234 ID47 [origin ip="10.0.0.1"] started`,
+/// and RFC 3164, such as `<34>Mar  1 12:00:00 host sshd[1234]: started`.
+///
+/// The priority is optional for RFC 3164 lines, as it is not written to files
+/// such as `/var/log/syslog`. Structured data is added to the context as one
+/// object per element.
+pub struct SyslogFormat<T: ?Sized = TextFormat> {
+    inner_format: T,
+}
+
+impl<T> SyslogFormat<T> {
+    pub fn new(inner_format: T) -> SyslogFormat<T> {
+        SyslogFormat { inner_format }
+    }
+}
+
+impl<T: InputFormat + ?Sized> InputFormat for SyslogFormat<T> {
+    fn parse_message(&self, line: &str, default_severity: Severity) -> Option<Message> {
+        let mut message = parse_rfc5424(line).or_else(|| parse_rfc3164(line))?;
+        message.severity = message.severity.or(default_severity);
+
+        if let Some(inner_message) = self
+            .inner_format
+            .parse_message(&message.text, Severity::Default)
+        {
+            message.merge_with(inner_message);
+        }
+
+        Some(message)
+    }
+}
+
+/// The name of a syslog facility code.
+pub fn facility_name(facility: u64) -> Option<&'static str> {
+    const FACILITIES: [&str; 24] = [
+        "kern",
+        "user",
+        "mail",
+        "daemon",
+        "auth",
+        "syslog",
+        "lpr",
+        "news",
+        "uucp",
+        "cron",
+        "authpriv",
+        "ftp",
+        "ntp",
+        "security",
+        "console",
+        "solaris-cron",
+        "local0",
+        "local1",
+        "local2",
+        "local3",
+        "local4",
+        "local5",
+        "local6",
+        "local7",
+    ];
+
+    FACILITIES.get(facility as usize).copied()
+}
+
+/// Create a message with the severity and facility of a syslog priority value.
+fn from_priority(priority: Option<&str>) -> Option<Message> {
+    let mut message = Message::default();
+
+    if let Some(priority) = priority {
+        let priority: u64 = priority.parse().ok().filter(|&priority| priority < 192)?;
+        message.severity = Severity::from_syslog(priority % 8);
+        if let Some(facility) = facility_name(priority / 8) {
+            message.add_context("facility", facility);
+        }
+    }
+
+    Some(message)
+}
+
+fn parse_rfc5424(line: &str) -> Option<Message> {
+    lazy_static! {
+        static ref HEADER: Regex =
+            Regex::new(r"^<(\d{1,3})>1 (\S+) (\S+) (\S+) (\S+) (\S+) ").unwrap();
+    }
+
+    let captures = HEADER.captures(line)?;
+    let mut message = from_priority(captures.get(1).map(|m| m.as_str()))?;
+
+    let time = captures.get(2)?.as_str();
+    if time != "-" {
+        message.time = Some(DateTime::parse_from_rfc3339(time).ok()?.into());
+    }
+
+    for (index, name) in [(3, "hostname"), (4, "app"), (5, "pid"), (6, "msgid")] {
+        let value = captures.get(index)?.as_str();
+        if value != "-" {
+            message.add_context(name, value);
+        }
+    }
+
+    let rest = &line[captures.get(0)?.end()..];
+    let rest = match rest.strip_prefix('-') {
+        Some(rest) => rest,
+        None => {
+            let (data, rest) = StructuredData::parse_token(rest).ok()?;
+            for element in data.0 {
+                message.add_context(element.id, element.value());
+            }
+            rest
+        }
+    };
+
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+
+    let text = rest.strip_prefix(' ').unwrap_or(rest);
+    message.text = text.trim_start_matches('\u{feff}').to_string();
+
+    Some(message)
+}
+
+fn parse_rfc3164(line: &str) -> Option<Message> {
+    lazy_static! {
+        static ref LINE: Regex = Regex::new(concat!(
+            r"^(?:<(\d{1,3})>)?",
+            r"([A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}|\d{4}-\d{2}-\d{2}T\S+) ",
+            r"(\S+) ",
+            r"(?:([^\s\[\]:]+)(?:\[([^\s\]]+)\])?: )?",
+            r"(.*)$",
+        ))
+        .unwrap();
+    }
+
+    let captures = LINE.captures(line)?;
+    let mut message = from_priority(captures.get(1).map(|m| m.as_str()))?;
+
+    let time = captures.get(2)?.as_str();
+    message.time = match DateTime::parse_from_rfc3339(time) {
+        Ok(time) => Some(time.into()),
+        Err(_) => Some(parse_time_without_year(time, Local::now())?),
+    };
+
+    message.add_context("hostname", captures.get(3)?.as_str());
+    if let Some(app) = captures.get(4) {
+        message.add_context("app", app.as_str());
+    }
+    if let Some(pid) = captures.get(5) {
+        message.add_context("pid", pid.as_str());
+    }
+
+    message.text = captures.get(6)?.as_str().to_string();
+
+    Some(message)
+}
+
+/// Parse a local time such as `Mar  1 12:00:00`, which is assumed to be from
+/// the last year before `now`.
+fn parse_time_without_year(time: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let parse = |year: i32| {
+        let time = NaiveDateTime::parse_from_str(&format!("{} {}", year, time), "%Y %b %e %T");
+        Local.from_local_datetime(&time.ok()?).earliest()
+    };
+
+    // allow for clocks that are slightly out of sync
+    match parse(now.year()) {
+        Some(time) if time > now + Duration::days(1) => parse(now.year() - 1),
+        time => time,
+    }
+}
+
+regex_token!(struct OpenBracket = r"\[");
+regex_token!(struct CloseBracket = r"\]");
+regex_token!(struct Space = r" ");
+regex_token!(struct Equals = r"=");
+regex_token!(struct SdName = r#"[^\s=\]"]+"#);
+
+/// The structured data elements of an RFC 5424 line.
+#[derive(Debug)]
+struct StructuredData<'a>(Vec<SdElement<'a>>);
+
+#[derive(Debug)]
+struct SdElement<'a> {
+    id: &'a str,
+    params: Vec<(&'a str, String)>,
+}
+
+impl SdElement<'_> {
+    /// The parameters of the element as an object, or `true` if there are none.
+    fn value(self) -> Value {
+        if self.params.is_empty() {
+            return Value::Bool(true);
+        }
+
+        let params: Map<_, _> = self
+            .params
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value)))
+            .collect();
+        Value::Object(params)
+    }
+}
+
+impl<'a> Token<'a> for StructuredData<'a> {
+    fn parse_token(mut s: &'a str) -> parse::Result<(StructuredData<'a>, &'a str)> {
+        let mut elements = Vec::new();
+        while s.starts_with('[') {
+            let (element, rest) = SdElement::parse_token(s)?;
+            elements.push(element);
+            s = rest;
+        }
+
+        if elements.is_empty() {
+            Err(ParseError::custom("Expected structured data"))
+        } else {
+            Ok((StructuredData(elements), s))
+        }
+    }
+}
+
+impl<'a> Token<'a> for SdElement<'a> {
+    fn parse_token(s: &'a str) -> parse::Result<(SdElement<'a>, &'a str)> {
+        let (_, s) = OpenBracket::parse_token(s)?;
+        let (id, mut s) = SdName::parse_token(s)?;
+
+        let mut params = Vec::new();
+        while let Ok((_, rest)) = Space::parse_token(s) {
+            let (name, rest) = SdName::parse_token(rest)?;
+            let (_, rest) = Equals::parse_token(rest)?;
+            let (ParamValue(value), rest) = ParamValue::parse_token(rest)?;
+            params.push((name.0, value));
+            s = rest;
+        }
+
+        let (_, s) = CloseBracket::parse_token(s)?;
+        Ok((SdElement { id: id.0, params }, s))
+    }
+}
+
+/// A quoted parameter value, where only `"`, `\` and `]` are escaped.
+#[derive(Debug)]
+struct ParamValue(String);
+
+impl<'a> Token<'a> for ParamValue {
+    fn parse_token(s: &'a str) -> parse::Result<(ParamValue, &'a str)> {
+        let mut iter = s.chars();
+
+        if iter.next() != Some('"') {
+            return Err(ParseError::custom("Invalid parameter value"));
+        }
+
+        let mut value = String::new();
+        while let Some(ch) = iter.next() {
+            match ch {
+                '"' => return Ok((ParamValue(value), iter.as_str())),
+                '\\' => match iter.next() {
+                    Some(ch @ '"') | Some(ch @ '\\') | Some(ch @ ']') => value.push(ch),
+                    Some(ch) => {
+                        value.push('\\');
+                        value.push(ch);
+                    }
+                    None => break,
+                },
+                _ => value.push(ch),
+            }
+        }
+
+        Err(ParseError::custom("Unterminated parameter value"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::json::{FieldMapping, JsonFormat};
+    use serde_json::json;
+
+    #[test]
+    fn rfc5424_lines() {
+        let format = SyslogFormat::new(TextFormat::new());
+
+        let line = concat!(
+            r#"<165>1 2020-03-01T12:00:00.003Z host app 1234 ID47 "#,
+            r#"[origin ip="10.0.0.1" note="a \"b\" \] c"][retry] "#,
+            "\u{feff}started",
+        );
+        let message = format.parse_message(line, Severity::Default).unwrap();
+        assert_eq!(message.text, "started");
+        assert_eq!(message.severity, Severity::Notice);
+        assert!(message.time.is_some());
+        assert_eq!(message.context["facility"], json!("local4"));
+        assert_eq!(message.context["hostname"], json!("host"));
+        assert_eq!(message.context["app"], json!("app"));
+        assert_eq!(message.context["pid"], json!("1234"));
+        assert_eq!(message.context["msgid"], json!("ID47"));
+        assert_eq!(
+            message.context["origin"],
+            json!({ "ip": "10.0.0.1", "note": "a \"b\" ] c" })
+        );
+        assert_eq!(message.context["retry"], json!(true));
+
+        let message = format
+            .parse_message("<11>1 - - - - - -", Severity::Default)
+            .unwrap();
+        assert_eq!(message.text, "");
+        assert_eq!(message.severity, Severity::Error);
+        assert_eq!(message.context.len(), 1);
+
+        assert!(format
+            .parse_message("<11>1 - - - - - [broken", Severity::Default)
+            .is_none());
+    }
+
+    #[test]
+    fn rfc3164_lines() {
+        let format =
+            SyslogFormat::new(JsonFormat::new(&FieldMapping::default(), TextFormat::new()));
+
+        let message = format
+            .parse_message(
+                "<34>Oct 11 22:14:15 mymachine su: 'su root' failed",
+                Severity::Default,
+            )
+            .unwrap();
+        assert_eq!(message.text, "'su root' failed");
+        assert_eq!(message.severity, Severity::Critical);
+        assert_eq!(message.context["facility"], json!("auth"));
+        assert_eq!(message.context["hostname"], json!("mymachine"));
+        assert_eq!(message.context["app"], json!("su"));
+
+        let message = format
+            .parse_message(
+                r#"Mar  1 12:00:00 web api[812]: {"level":"warn","msg":"slow"}"#,
+                Severity::Info,
+            )
+            .unwrap();
+        assert_eq!(message.text, "slow");
+        assert_eq!(message.severity, Severity::Warning);
+        assert_eq!(message.context["pid"], json!("812"));
+
+        assert!(format
+            .parse_message("Starting server on port 8080", Severity::Default)
+            .is_none());
+        assert!(format
+            .parse_message("<999>Mar  1 12:00:00 host x", Severity::Default)
+            .is_none());
+    }
+
+    #[test]
+    fn times_without_year() {
+        let now = Local.with_ymd_and_hms(2020, 1, 1, 12, 0, 0).unwrap();
+        let time = |time| parse_time_without_year(time, now).unwrap();
+
+        assert_eq!(
+            time("Jan  1 11:00:00"),
+            Local.with_ymd_and_hms(2020, 1, 1, 11, 0, 0).unwrap()
+        );
+        assert_eq!(
+            time("Dec 31 23:00:00"),
+            Local.with_ymd_and_hms(2019, 12, 31, 23, 0, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,439 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use super::theme::TextStyle;
+use super::timestamp::{self, TimeDisplay};
+use super::{style::*, OutputFormat};
+use crate::message::{Message, Value};
+use crate::parse::{self, parse, regex_token, Parse, ParseBuffer, ParseError};
+use crate::DisplayOptions;
+
+/// Prints each message with a user-defined template.
+///
+/// Fields are written as `{name}` or `{name:format}`, where the name is
+/// `time`, `level`, `msg`, `source`, `repeat` (the number of collapsed
+/// repeats), `ctx` (the whole context as JSON) or `ctx.<key>`. The time is
+/// shown as set by `--time-format`, unless a strftime format is given, such as
+/// `{time:%H:%M}`. Other fields take an optional alignment (`<`, `>` or `^`),
+/// a minimum width and a maximum width after a dot, such as `{level:>5}` or
+/// `{ctx.user:10.10}`.
+///
+/// `{?field}...{/}` only prints its contents if the field is present, and
+/// `{#style}...{/}` prints its contents in a style. The style is the name of a
+/// theme element (`severity`, `timestamp`, `level`, `separator`, `message`,
+/// `context-key` or `context-value`) or a style such as `bold red`. Literal
+/// braces are written as `{{` and `}}`.
+pub struct TemplateFormat {
+    style: AnyStyle,
+    template: Template,
+    time: TimeDisplay,
+}
+
+impl TemplateFormat {
+    pub fn new<S: Style>(style: S, opts: &DisplayOptions) -> Result<TemplateFormat, String> {
+        let template = opts
+            .template
+            .as_deref()
+            .ok_or("The template output format requires --template")?;
+
+        Ok(TemplateFormat {
+            style: style.into(),
+            template: Template::parse(template)?,
+            time: TimeDisplay::new(opts)?,
+        })
+    }
+
+    fn write_nodes<'t, W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        nodes: &'t [Node],
+        message: &Message,
+        time: Option<&str>,
+        styles: &mut Vec<&'t TemplateStyle>,
+    ) -> io::Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => write!(writer, "{}", text)?,
+                Node::Field(field, format) => {
+                    if let Some(value) = self.render(field, format, message, time) {
+                        write!(writer, "{}", format.pad(value))?;
+                    }
+                }
+                Node::Conditional(field, body) => {
+                    let is_present = self
+                        .render(field, &FieldFormat::default(), message, time)
+                        .is_some_and(|value| !value.is_empty());
+                    if is_present {
+                        self.write_nodes(writer, body, message, time, styles)?;
+                    }
+                }
+                Node::Styled(style, body) => {
+                    self.apply_style(writer, style, message)?;
+                    styles.push(style);
+                    self.write_nodes(writer, body, message, time, styles)?;
+                    styles.pop();
+
+                    // restore the styles of enclosing sections
+                    self.style.reset(writer)?;
+                    for style in styles.iter() {
+                        self.apply_style(writer, style, message)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_style<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        style: &TemplateStyle,
+        message: &Message,
+    ) -> io::Result<()> {
+        match style {
+            TemplateStyle::Element(element) => {
+                self.style.element(writer, *element, message.severity)
+            }
+            TemplateStyle::Text(text_style) => self.style.text_style(writer, text_style),
+        }
+    }
+}
+
+impl OutputFormat for TemplateFormat {
+    fn print_message<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        message: &Message,
+    ) -> io::Result<()> {
+        // format the time once, since relative times depend on earlier messages
+        let time = message.time.map(|time| self.time.format(time));
+        let time = time.as_deref();
+
+        self.write_nodes(writer, &self.template.0, message, time, &mut Vec::new())?;
+        writeln!(writer)
+    }
+}
+
+#[derive(Debug)]
+struct Template(Vec<Node>);
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Field(Field, FieldFormat),
+    Conditional(Field, Vec<Node>),
+    Styled(TemplateStyle, Vec<Node>),
+}
+
+#[derive(Debug)]
+enum Field {
+    Time,
+    Level,
+    Message,
+    Source,
+    Repeat,
+    Context(Option<String>),
+}
+
+#[derive(Debug, Default)]
+struct FieldFormat {
+    time_format: Option<String>,
+    align: Align,
+    width: Option<usize>,
+    max_width: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum Align {
+    #[default]
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug)]
+enum TemplateStyle {
+    Element(Element),
+    Text(TextStyle),
+}
+
+impl Template {
+    fn parse(template: &str) -> Result<Template, String> {
+        parse::<Template>(template)
+            .map_err(|err| format!("Invalid template {:?}: {}", template, err))
+    }
+}
+
+impl TemplateFormat {
+    fn render(
+        &self,
+        field: &Field,
+        format: &FieldFormat,
+        message: &Message,
+        time: Option<&str>,
+    ) -> Option<String> {
+        match field {
+            Field::Time => match &format.time_format {
+                Some(time_format) => message
+                    .time
+                    .map(|time| self.time.format_with(time, time_format)),
+                None => time.map(str::to_string),
+            },
+            Field::Level => Some(message.severity.to_string().to_uppercase()),
+            Field::Message => Some(message.text.clone()),
+            Field::Source => message.source.clone(),
+            Field::Repeat => message
+                .repeat
+                .as_ref()
+                .map(|repeat| repeat.count.to_string()),
+            Field::Context(None) if message.context.is_empty() => None,
+            Field::Context(None) => {
+                let context: BTreeMap<_, _> = message.context.iter().collect();
+                serde_json::to_string(&context).ok()
+            }
+            Field::Context(Some(key)) => match message.lookup(key)? {
+                Value::Null => None,
+                Value::String(value) => Some(value.clone()),
+                value => Some(value.to_string()),
+            },
+        }
+    }
+}
+
+impl FieldFormat {
+    fn pad(&self, mut value: String) -> String {
+        let len = value.chars().count();
+
+        if let Some(max_width) = self.max_width.filter(|&max_width| len > max_width) {
+            value = value.chars().take(max_width.saturating_sub(1)).collect();
+            if max_width > 0 {
+                value.push('…');
+            }
+        }
+
+        match self.width {
+            Some(width) => match self.align {
+                Align::Left => format!("{:<width$}", value, width = width),
+                Align::Right => format!("{:>width$}", value, width = width),
+                Align::Center => format!("{:^width$}", value, width = width),
+            },
+            None => value,
+        }
+    }
+}
+
+regex_token!(struct Literal = r"^([^{}]|\{\{|\}\})+");
+regex_token!(struct Tag = r"^\{[^{}]*\}");
+
+impl<'a> Parse<'a> for Template {
+    fn parse(buf: &mut ParseBuffer<'a>) -> parse::Result<Template> {
+        match parse_nodes(buf)? {
+            (nodes, false) => Ok(Template(nodes)),
+            (_, true) => Err(ParseError::custom("Unexpected {/}")),
+        }
+    }
+}
+
+/// Parse nodes up to the end of the input or a closing `{/}`, and return
+/// whether the closing tag was found.
+fn parse_nodes(buf: &mut ParseBuffer) -> parse::Result<(Vec<Node>, bool)> {
+    let mut nodes = Vec::new();
+
+    while !buf.is_empty() {
+        if let Some(text) = buf.parse::<Option<Literal>>()? {
+            let text = text.as_ref().replace("{{", "{").replace("}}", "}");
+            nodes.push(Node::Text(text));
+            continue;
+        }
+
+        let tag = buf.parse::<Tag>()?;
+        let tag = tag.as_ref();
+        let tag = tag[1..tag.len() - 1].trim();
+
+        if tag == "/" {
+            return Ok((nodes, true));
+        } else if let Some(field) = tag.strip_prefix('?') {
+            let field = parse_field(field.trim())?;
+            nodes.push(Node::Conditional(field, parse_body(buf)?));
+        } else if let Some(style) = tag.strip_prefix('#') {
+            let style = parse_style(style.trim())?;
+            nodes.push(Node::Styled(style, parse_body(buf)?));
+        } else {
+            let (field, format) = match tag.find(':') {
+                Some(index) => (&tag[..index], Some(&tag[index + 1..])),
+                None => (tag, None),
+            };
+            let field = parse_field(field)?;
+            let format = match format {
+                Some(format) => parse_format(&field, format)?,
+                None => FieldFormat::default(),
+            };
+            nodes.push(Node::Field(field, format));
+        }
+    }
+
+    Ok((nodes, false))
+}
+
+fn parse_body(buf: &mut ParseBuffer) -> parse::Result<Vec<Node>> {
+    match parse_nodes(buf)? {
+        (nodes, true) => Ok(nodes),
+        (_, false) => Err(ParseError::custom("Missing {/}")),
+    }
+}
+
+fn parse_field(name: &str) -> parse::Result<Field> {
+    match name {
+        "time" => Ok(Field::Time),
+        "level" => Ok(Field::Level),
+        "msg" | "message" => Ok(Field::Message),
+        "source" => Ok(Field::Source),
+        "repeat" => Ok(Field::Repeat),
+        "ctx" => Ok(Field::Context(None)),
+        name => match name.strip_prefix("ctx.") {
+            Some(key) if !key.is_empty() => Ok(Field::Context(Some(key.to_string()))),
+            _ => Err(ParseError::custom(format!("Unknown field {}", name))),
+        },
+    }
+}
+
+fn parse_format(field: &Field, format: &str) -> parse::Result<FieldFormat> {
+    lazy_static! {
+        static ref FORMAT: Regex = Regex::new(r"^([<>^])?(\d+)?(?:\.(\d+))?$").unwrap();
+    }
+
+    if let Field::Time = field {
+        timestamp::validate(format).map_err(ParseError::custom)?;
+
+        return Ok(FieldFormat {
+            time_format: Some(format.to_string()),
+            ..FieldFormat::default()
+        });
+    }
+
+    let captures = FORMAT
+        .captures(format)
+        .ok_or_else(|| ParseError::custom(format!("Invalid field format {}", format)))?;
+    let number = |index: usize| {
+        captures
+            .get(index)
+            .map(|m| m.as_str().parse().map_err(ParseError::custom))
+            .transpose()
+    };
+
+    Ok(FieldFormat {
+        time_format: None,
+        align: match captures.get(1).map(|m| m.as_str()) {
+            Some(">") => Align::Right,
+            Some("^") => Align::Center,
+            _ => Align::Left,
+        },
+        width: number(2)?,
+        max_width: number(3)?,
+    })
+}
+
+fn parse_style(style: &str) -> parse::Result<TemplateStyle> {
+    let element = match style {
+        "severity" => Element::Severity,
+        "timestamp" => Element::Timestamp,
+        "level" => Element::Level,
+        "separator" => Element::Separator,
+        "message" => Element::Message,
+        "context-key" => Element::ContextKey,
+        "context-value" => Element::ContextValue,
+        style => {
+            return style
+                .parse()
+                .map(TemplateStyle::Text)
+                .map_err(ParseError::custom)
+        }
+    };
+
+    Ok(TemplateStyle::Element(element))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::theme::Theme;
+    use crate::message::Severity;
+    use chrono::{Local, TimeZone};
+    use structopt::StructOpt;
+
+    fn render(template: &str, style: impl Style, message: &Message) -> String {
+        let format = TemplateFormat {
+            style: style.into(),
+            template: Template::parse(template).unwrap(),
+            time: TimeDisplay::new(&DisplayOptions::from_iter(&["test"])).unwrap(),
+        };
+
+        let mut output = Vec::new();
+        format.print_message(&mut output, message).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn message() -> Message {
+        let mut message = Message::from_text("request failed", Severity::Warning);
+        message.time = Some(Local.with_ymd_and_hms(2020, 3, 1, 14, 5, 9).unwrap());
+        message.add_context("service", "api");
+        message.add_context("http", serde_json::json!({ "status": 503 }));
+        message
+    }
+
+    #[test]
+    fn fields() {
+        let message = message();
+        assert_eq!(
+            render(
+                "{time:%H:%M:%S} {level:5}|{level:>9}| [{ctx.service}] {msg} {{{ctx.http.status}}}",
+                PlainStyle,
+                &message
+            ),
+            "14:05:09 WARNING|  WARNING| [api] request failed {503}\n"
+        );
+        assert_eq!(
+            render("{msg:^10.8}|{ctx.service:4}|{source}", PlainStyle, &message),
+            " request… |api |\n"
+        );
+        assert_eq!(
+            render("{ctx}", PlainStyle, &message),
+            "{\"http\":{\"status\":503},\"service\":\"api\"}\n"
+        );
+    }
+
+    #[test]
+    fn sections() {
+        let message = message();
+        assert_eq!(
+            render(
+                "{?ctx.service}[{ctx.service}] {/}{?source}({source}) {/}{msg}",
+                PlainStyle,
+                &message
+            ),
+            "[api] request failed\n"
+        );
+        assert_eq!(
+            render(
+                "{#bold}{#level}{level}{/}:{/} {msg}",
+                ColoredStyle::new(Theme::default()),
+                &message
+            ),
+            "\x1b[1m\x1b[2;33mWARNING\x1b[0m\x1b[1m:\x1b[0m request failed\n"
+        );
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(Template::parse("{unknown}").is_err());
+        assert!(Template::parse("{?msg} no end").is_err());
+        assert!(Template::parse("{/}").is_err());
+        assert!(Template::parse("{msg:x}").is_err());
+        assert!(Template::parse("{#blinking}x{/}").is_err());
+        assert!(Template::parse("unbalanced }").is_err());
+        assert!(Template::parse("{time:%Q}").is_err());
+    }
+}
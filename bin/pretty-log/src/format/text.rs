@@ -1,13 +1,29 @@
+use chrono::SecondsFormat;
 use std::io::{self, Write};
 
-use super::{InputFormat, OutputFormat};
+use super::{timestamp::TimeDisplay, InputFormat, OutputFormat};
 use crate::message::{Message, Severity};
+use crate::DisplayOptions;
 
-pub struct TextFormat {}
+pub struct TextFormat {
+    time: Option<TimeDisplay>,
+}
 
 impl TextFormat {
     pub fn new() -> TextFormat {
-        TextFormat {}
+        TextFormat { time: None }
+    }
+
+    /// Create an output format that also prints timestamps, if a time format
+    /// is given.
+    pub fn with_options(opts: &DisplayOptions) -> Result<TextFormat, String> {
+        let time = if opts.time_format.is_some() || opts.utc {
+            Some(TimeDisplay::new(opts)?)
+        } else {
+            None
+        };
+
+        Ok(TextFormat { time })
     }
 }
 
@@ -23,6 +39,29 @@ impl OutputFormat for TextFormat {
         writer: &mut W,
         message: &Message,
     ) -> io::Result<()> {
-        writeln!(writer, "{}", message.text)
+        if let Some(display) = &self.time {
+            match message.time {
+                Some(time) => write!(writer, "{} ", display.format(time))?,
+                None => write!(writer, "{:width$} ", "", width = display.width())?,
+            }
+        }
+
+        write!(writer, "{}", message.text)?;
+        if let Some(repeat) = &message.repeat {
+            write!(writer, " (×{}", repeat.count)?;
+            if let Some(time) = repeat.last_time {
+                match &self.time {
+                    Some(display) => write!(writer, " until {}", display.format_absolute(time))?,
+                    None => write!(
+                        writer,
+                        " until {}",
+                        time.to_rfc3339_opts(SecondsFormat::AutoSi, false)
+                    )?,
+                }
+            }
+            write!(writer, ")")?;
+        }
+
+        writeln!(writer)
     }
 }
@@ -0,0 +1,346 @@
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+use crate::message::Severity;
+
+/// A terminal colour.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Color {
+    /// One of the 16 standard colours, where 8-15 are the bright variants.
+    Ansi(u8),
+
+    /// One of the 256 colours of the extended palette.
+    Fixed(u8),
+
+    /// A 24-bit colour.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn write_sgr(self, codes: &mut Vec<String>, background: bool) {
+        let offset = if background { 10 } else { 0 };
+        let extended = if background { 48 } else { 38 };
+
+        match self {
+            Color::Ansi(n) if n < 8 => codes.push((30 + offset + n).to_string()),
+            Color::Ansi(n) => codes.push((90 + offset + n - 8).to_string()),
+            Color::Fixed(n) => codes.push(format!("{};5;{}", extended, n)),
+            Color::Rgb(r, g, b) => codes.push(format!("{};2;{};{};{}", extended, r, g, b)),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parse a colour name (such as `red` or `bright-blue`), a palette index
+    /// from 0 to 255 or a hex colour (such as `#ff8800`).
+    fn from_str(s: &str) -> Result<Color, String> {
+        const NAMES: &[&str] = &[
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+
+        let invalid = || format!("Invalid color: {}", s);
+
+        if let Some(index) = NAMES.iter().position(|&name| name == s) {
+            Ok(Color::Ansi(index as u8))
+        } else if let Some(index) = s
+            .strip_prefix("bright-")
+            .and_then(|name| NAMES.iter().position(|&n| n == name))
+        {
+            Ok(Color::Ansi(index as u8 + 8))
+        } else if let Some(hex) = s.strip_prefix('#') {
+            let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            if hex.len() != 6 {
+                return Err(invalid());
+            }
+            Ok(Color::Rgb(
+                (value >> 16) as u8,
+                (value >> 8) as u8,
+                value as u8,
+            ))
+        } else {
+            s.parse().map(Color::Fixed).map_err(|_| invalid())
+        }
+    }
+}
+
+/// The colours and attributes to display some text with.
+///
+/// Written as a list of words, such as `bold red`, `dim 244` or
+/// `underline #ff8800 on black`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextStyle {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl TextStyle {
+    pub fn fg(color: Color) -> TextStyle {
+        TextStyle {
+            fg: Some(color),
+            ..TextStyle::default()
+        }
+    }
+
+    /// Layer `other` on top of this style.
+    pub fn with(&self, other: &TextStyle) -> TextStyle {
+        TextStyle {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            bold: self.bold || other.bold,
+            dim: self.dim || other.dim,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+        }
+    }
+
+    /// Get the ANSI escape sequence that enables this style, if any.
+    pub fn to_sgr(&self) -> Option<String> {
+        let mut codes = Vec::new();
+
+        for (enabled, code) in &[
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+        ] {
+            if *enabled {
+                codes.push(code.to_string());
+            }
+        }
+        if let Some(fg) = self.fg {
+            fg.write_sgr(&mut codes, false);
+        }
+        if let Some(bg) = self.bg {
+            bg.write_sgr(&mut codes, true);
+        }
+
+        if codes.is_empty() {
+            None
+        } else {
+            Some(format!("\x1b[{}m", codes.join(";")))
+        }
+    }
+}
+
+impl FromStr for TextStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TextStyle, String> {
+        let mut style = TextStyle::default();
+        let mut words = s.split_whitespace();
+
+        while let Some(word) = words.next() {
+            match word {
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "on" => {
+                    let color = words
+                        .next()
+                        .ok_or_else(|| format!("Missing background color: {}", s))?;
+                    style.bg = Some(color.parse()?);
+                }
+                color => style.fg = Some(color.parse()?),
+            }
+        }
+
+        Ok(style)
+    }
+}
+
+impl<'de> Deserialize<'de> for TextStyle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TextStyle, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The styles of each severity level, where `critical` also applies to the
+/// alert and emergency levels.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SeverityStyles {
+    pub critical: TextStyle,
+    pub error: TextStyle,
+    pub warning: TextStyle,
+    pub notice: TextStyle,
+    pub info: TextStyle,
+    pub debug: TextStyle,
+    pub default: TextStyle,
+}
+
+impl Default for SeverityStyles {
+    fn default() -> SeverityStyles {
+        SeverityStyles {
+            critical: TextStyle::fg(Color::Ansi(1)),
+            error: TextStyle::fg(Color::Ansi(1)),
+            warning: TextStyle::fg(Color::Ansi(3)),
+            notice: TextStyle::fg(Color::Ansi(4)),
+            info: TextStyle::fg(Color::Ansi(4)),
+            debug: TextStyle::fg(Color::Ansi(2)),
+            default: TextStyle::default(),
+        }
+    }
+}
+
+/// A rule for highlighting the values of a context key.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Highlight {
+    pub key: String,
+
+    /// The style of the value, or a colour picked from the palette by the
+    /// value if not given, so that equal values have equal colours.
+    #[serde(default)]
+    pub style: Option<TextStyle>,
+}
+
+/// The styles of each part of a message.
+///
+/// The style of the message's severity is the base for the other styles.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Theme {
+    pub severity: SeverityStyles,
+    pub timestamp: TextStyle,
+    pub level: TextStyle,
+    pub separator: TextStyle,
+    pub message: TextStyle,
+    pub context_key: TextStyle,
+    pub context_value: TextStyle,
+
+    /// Colours to pick from for input labels and highlighted values.
+    pub palette: Vec<Color>,
+
+    pub highlight: Vec<Highlight>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        let dim = TextStyle {
+            dim: true,
+            ..TextStyle::default()
+        };
+
+        Theme {
+            severity: SeverityStyles::default(),
+            timestamp: dim.clone(),
+            level: dim.clone(),
+            separator: dim.clone(),
+            message: TextStyle {
+                bold: true,
+                ..TextStyle::default()
+            },
+            context_key: dim.clone(),
+            context_value: dim,
+            palette: [6, 5, 2, 3, 4, 14, 13, 10, 11, 12]
+                .iter()
+                .map(|&n| Color::Ansi(n))
+                .collect(),
+            highlight: Vec::new(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn severity(&self, severity: Severity) -> &TextStyle {
+        match severity {
+            Severity::Critical | Severity::Alert | Severity::Emergency => &self.severity.critical,
+            Severity::Error => &self.severity.error,
+            Severity::Warning => &self.severity.warning,
+            Severity::Notice => &self.severity.notice,
+            Severity::Info => &self.severity.info,
+            Severity::Debug => &self.severity.debug,
+            Severity::Default => &self.severity.default,
+        }
+    }
+
+    /// Pick a colour from the palette by hashing `value`.
+    pub fn hashed(&self, value: &str) -> TextStyle {
+        let hash = value.bytes().fold(0u32, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as u32)
+        });
+
+        match self.palette.len() {
+            0 => TextStyle::default(),
+            len => TextStyle::fg(self.palette[hash as usize % len]),
+        }
+    }
+
+    /// Get the highlight style of a context value, if there is one.
+    pub fn highlight(&self, key: &str, value: &str) -> Option<TextStyle> {
+        let rule = self.highlight.iter().find(|rule| rule.key == key)?;
+        Some(match &rule.style {
+            Some(style) => style.clone(),
+            None => self.hashed(value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_styles() {
+        assert_eq!(
+            "bold red".parse::<TextStyle>().unwrap().to_sgr().unwrap(),
+            "\x1b[1;31m"
+        );
+        assert_eq!(
+            "bright-cyan on 236"
+                .parse::<TextStyle>()
+                .unwrap()
+                .to_sgr()
+                .unwrap(),
+            "\x1b[96;48;5;236m"
+        );
+        assert_eq!(
+            "underline #ff8800"
+                .parse::<TextStyle>()
+                .unwrap()
+                .to_sgr()
+                .unwrap(),
+            "\x1b[4;38;2;255;136;0m"
+        );
+        assert_eq!("".parse::<TextStyle>().unwrap().to_sgr(), None);
+        assert!("blinking".parse::<TextStyle>().is_err());
+        assert!("on".parse::<TextStyle>().is_err());
+        assert!("#ff88".parse::<TextStyle>().is_err());
+    }
+
+    #[test]
+    fn highlight_rules() {
+        let theme: Theme = serde_json::from_str(
+            r#"{
+                "severity": { "error": "bright-red" },
+                "highlight": [
+                    { "key": "request_id" },
+                    { "key": "user", "style": "underline" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(theme.severity(Severity::Error).fg, Some(Color::Ansi(9)));
+        assert_eq!(theme.severity(Severity::Warning).fg, Some(Color::Ansi(3)));
+
+        let style = theme.highlight("request_id", "abc").unwrap();
+        assert_eq!(theme.highlight("request_id", "abc"), Some(style));
+        assert!(theme.highlight("user", "bob").unwrap().underline);
+        assert_eq!(theme.highlight("other", "abc"), None);
+    }
+}
@@ -0,0 +1,171 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use std::sync::Mutex;
+
+use crate::DisplayOptions;
+
+const DEFAULT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%:z";
+const TIME_ONLY_FORMAT: &str = "%H:%M:%S%.3f";
+
+/// How message timestamps are displayed.
+///
+/// The format is `iso` (the default), `time` for the time of day only,
+/// `relative` for the time since the previous message, `elapsed` for the time
+/// since the first message, or a strftime format. Absolute times are shown in
+/// local time, or in UTC if `utc` is set.
+pub struct TimeDisplay {
+    format: TimeFormat,
+    utc: bool,
+    width: usize,
+    state: Mutex<RelativeState>,
+}
+
+#[derive(Debug, PartialEq)]
+enum TimeFormat {
+    Strftime(String),
+    SincePrevious,
+    SinceFirst,
+}
+
+#[derive(Default)]
+struct RelativeState {
+    first: Option<DateTime<Local>>,
+    previous: Option<DateTime<Local>>,
+}
+
+/// The width of relative times, such as `+1.153s`.
+const RELATIVE_WIDTH: usize = 10;
+
+impl TimeDisplay {
+    pub fn new(opts: &DisplayOptions) -> Result<TimeDisplay, String> {
+        let format = match opts.time_format.as_deref().unwrap_or("iso") {
+            "iso" => TimeFormat::Strftime(DEFAULT_FORMAT.to_string()),
+            "time" => TimeFormat::Strftime(TIME_ONLY_FORMAT.to_string()),
+            "relative" => TimeFormat::SincePrevious,
+            "elapsed" => TimeFormat::SinceFirst,
+            format if format.contains('%') => TimeFormat::Strftime(validate(format)?.to_string()),
+            format => return Err(format!("Unknown time format: {}", format)),
+        };
+
+        let mut display = TimeDisplay {
+            format,
+            utc: opts.utc,
+            width: RELATIVE_WIDTH,
+            state: Mutex::new(RelativeState::default()),
+        };
+
+        if let TimeFormat::Strftime(format) = &display.format {
+            // use the width of a time with two-digit fields and long names
+            let sample = Local.with_ymd_and_hms(2000, 12, 27, 23, 59, 59).unwrap();
+            display.width = display.format_with(sample, format).chars().count();
+        }
+
+        Ok(display)
+    }
+
+    /// The (typical) number of characters of a formatted time.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Format the time of a message.
+    ///
+    /// This should be called once for each message with a time, in order, as
+    /// relative times depend on earlier messages.
+    pub fn format(&self, time: DateTime<Local>) -> String {
+        let format = match &self.format {
+            TimeFormat::Strftime(format) => return self.format_with(time, format),
+            format => format,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let since = match format {
+            TimeFormat::SincePrevious => state.previous.unwrap_or(time),
+            _ => *state.first.get_or_insert(time),
+        };
+        state.previous = Some(time);
+
+        let delta = (time - since).num_milliseconds() as f64 / 1000.0;
+        format!("{:>+width$.3}s", delta, width = RELATIVE_WIDTH - 1)
+    }
+
+    /// Format a time without affecting relative times, such as the time of the
+    /// last of several repeated messages. Relative formats show the time of day.
+    pub fn format_absolute(&self, time: DateTime<Local>) -> String {
+        match &self.format {
+            TimeFormat::Strftime(format) => self.format_with(time, format),
+            _ => self.format_with(time, TIME_ONLY_FORMAT),
+        }
+    }
+
+    /// Format a time with a strftime format, in local time or UTC.
+    pub fn format_with(&self, time: DateTime<Local>, format: &str) -> String {
+        if self.utc {
+            time.with_timezone(&Utc).format(format).to_string()
+        } else {
+            time.format(format).to_string()
+        }
+    }
+}
+
+/// Check that a strftime format is valid.
+pub fn validate(format: &str) -> Result<&str, String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        Err(format!("Invalid time format: {}", format))
+    } else {
+        Ok(format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use structopt::StructOpt;
+
+    fn time_display(args: &[&str]) -> TimeDisplay {
+        let args = std::iter::once("test").chain(args.iter().cloned());
+        TimeDisplay::new(&DisplayOptions::from_iter(args)).unwrap()
+    }
+
+    #[test]
+    fn absolute_times() {
+        let time = DateTime::parse_from_rfc3339("2020-03-01T14:05:09.250+02:00").unwrap();
+
+        let display = time_display(&["--utc"]);
+        assert_eq!(display.format(time.into()), "2020-03-01T12:05:09.250+00:00");
+        assert_eq!(display.width(), 29);
+
+        let display = time_display(&["--utc", "--time-format", "time"]);
+        assert_eq!(display.format(time.into()), "12:05:09.250");
+        assert_eq!(display.width(), 12);
+
+        let display = time_display(&["--utc", "--time-format", "%b %e %H:%M"]);
+        assert_eq!(display.format(time.into()), "Mar  1 12:05");
+
+        let opts = DisplayOptions::from_iter(&["test", "--time-format", "%Q"]);
+        assert!(TimeDisplay::new(&opts).is_err());
+        let opts = DisplayOptions::from_iter(&["test", "--time-format", "soon"]);
+        assert!(TimeDisplay::new(&opts).is_err());
+    }
+
+    #[test]
+    fn relative_times() {
+        let start = Local.with_ymd_and_hms(2020, 3, 1, 14, 5, 9).unwrap();
+        let times = [0, 153, 1153, 61153].map(|ms| start + Duration::milliseconds(ms));
+
+        let display = time_display(&["--time-format", "relative"]);
+        let output: Vec<_> = times.iter().map(|&time| display.format(time)).collect();
+        assert_eq!(
+            output,
+            vec!["   +0.000s", "   +0.153s", "   +1.000s", "  +60.000s"]
+        );
+
+        let display = time_display(&["--time-format", "elapsed"]);
+        let output: Vec<_> = times.iter().map(|&time| display.format(time)).collect();
+        assert_eq!(
+            output,
+            vec!["   +0.000s", "   +0.153s", "   +1.153s", "  +61.153s"]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Rules for attaching continuation lines, such as stack traces, to the
+/// preceding message.
+pub struct LineGrouping {
+    heuristics: bool,
+    patterns: Vec<Regex>,
+}
+
+impl LineGrouping {
+    pub fn new(heuristics: bool, patterns: Vec<Regex>) -> LineGrouping {
+        LineGrouping {
+            heuristics,
+            patterns,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.heuristics || !self.patterns.is_empty()
+    }
+
+    /// Create the grouping state for a single input stream.
+    pub fn start(&self) -> GroupState<'_> {
+        GroupState {
+            grouping: self,
+            mode: Mode::Default,
+        }
+    }
+}
+
+pub struct GroupState<'a> {
+    grouping: &'a LineGrouping,
+    mode: Mode,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Mode {
+    Default,
+
+    /// Inside a Python traceback, which ends with an unindented exception line.
+    PythonTraceback,
+
+    /// After a Rust panic, where the panic payload may follow on its own line.
+    RustPanic {
+        expect_payload: bool,
+    },
+}
+
+lazy_static! {
+    static ref JAVA_CONTINUATION: Regex =
+        Regex::new(r"^(at |Caused by: |Suppressed: |\.\.\. \d+ (more|common frames omitted))")
+            .unwrap();
+    static ref PYTHON_TRACEBACK: Regex = Regex::new(
+        r"^(Traceback \(most recent call last\):|During handling of the above exception|The above exception was the direct cause)"
+    )
+    .unwrap();
+    static ref PYTHON_EXCEPTION: Regex = Regex::new(r"^[A-Za-z_][\w.]*(: .*)?$").unwrap();
+    static ref RUST_PANIC: Regex = Regex::new(r"^thread '.*' panicked at ").unwrap();
+    static ref RUST_CONTINUATION: Regex = Regex::new(r"^(note: |stack backtrace:)").unwrap();
+}
+
+impl GroupState<'_> {
+    /// Check whether `line` continues the previous message.
+    pub fn is_continuation(&mut self, line: &str) -> bool {
+        if self.grouping.patterns.iter().any(|re| re.is_match(line)) {
+            return true;
+        }
+
+        if !self.grouping.heuristics {
+            return false;
+        }
+
+        let mode = self.mode;
+        self.mode = Mode::Default;
+
+        if line.trim().is_empty() {
+            return false;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            self.mode = match mode {
+                Mode::RustPanic { .. } => Mode::RustPanic {
+                    expect_payload: false,
+                },
+                mode => mode,
+            };
+            return true;
+        }
+
+        if PYTHON_TRACEBACK.is_match(line) {
+            self.mode = Mode::PythonTraceback;
+            return true;
+        }
+
+        if RUST_PANIC.is_match(line) {
+            self.mode = Mode::RustPanic {
+                expect_payload: line.ends_with(':'),
+            };
+            return false;
+        }
+
+        match mode {
+            Mode::PythonTraceback if PYTHON_EXCEPTION.is_match(line) => return true,
+            Mode::RustPanic { expect_payload }
+                if expect_payload || RUST_CONTINUATION.is_match(line) =>
+            {
+                self.mode = Mode::RustPanic {
+                    expect_payload: false,
+                };
+                return true;
+            }
+            _ => (),
+        }
+
+        JAVA_CONTINUATION.is_match(line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn group(grouping: &LineGrouping, input: &str) -> Vec<String> {
+        let mut state = grouping.start();
+        let mut groups: Vec<String> = Vec::new();
+
+        for line in input.lines() {
+            let is_continuation = state.is_continuation(line);
+            match groups.last_mut() {
+                Some(group) if is_continuation => {
+                    group.push('\n');
+                    group.push_str(line);
+                }
+                _ => groups.push(line.to_string()),
+            }
+        }
+
+        groups
+    }
+
+    #[test]
+    fn java_stack_trace() {
+        let input = "\
+Exception in thread \"main\" java.lang.IllegalStateException: boom
+\tat com.example.Main.run(Main.java:10)
+\tat com.example.Main.main(Main.java:5)
+Caused by: java.io.IOException: disk
+\t... 2 more
+next message";
+
+        let groups = group(&LineGrouping::new(true, vec![]), input);
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].ends_with("\t... 2 more"));
+        assert_eq!(groups[1], "next message");
+    }
+
+    #[test]
+    fn python_traceback() {
+        let input = "\
+ERROR request failed
+Traceback (most recent call last):
+  File \"app.py\", line 3, in <module>
+    main()
+ValueError: invalid literal
+INFO next";
+
+        let groups = group(&LineGrouping::new(true, vec![]), input);
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].ends_with("ValueError: invalid literal"));
+    }
+
+    #[test]
+    fn rust_panic() {
+        let input = "\
+starting
+thread 'main' panicked at src/main.rs:2:5:
+explicit panic
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+done";
+
+        let groups = group(&LineGrouping::new(true, vec![]), input);
+        assert_eq!(groups.len(), 3);
+        assert!(groups[1].starts_with("thread 'main' panicked"));
+        assert!(groups[1].ends_with("backtrace"));
+        assert_eq!(groups[2], "done");
+    }
+
+    #[test]
+    fn custom_patterns() {
+        let grouping = LineGrouping::new(false, vec![Regex::new(r"^\|").unwrap()]);
+        let groups = group(&grouping, "a\n| b\n  c\n| d");
+        assert_eq!(groups, vec!["a\n| b", "  c\n| d"]);
+    }
+}
@@ -1,35 +1,294 @@
+mod collapse;
+mod config;
 mod ext;
+mod filter;
 mod format;
+mod group;
+mod merge;
 mod message;
+mod pager;
 mod parse;
+mod pty;
+mod signal;
+mod source;
+mod time;
 mod utils;
 
+use crossbeam::channel::{self, Receiver, RecvTimeoutError};
 use crossbeam::scope;
+use serde::Deserialize;
+use std::env;
 use std::ffi::OsStr;
+use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Write};
-use std::process::{Command, ExitStatus, Stdio};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::str::FromStr;
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 
-use crate::format::{style::*, InputFormat, OutputFormat};
+use crate::collapse::{Collapser, Mask, Normalizer};
+use crate::config::Config;
+use crate::filter::Filter;
+use crate::format::{style::*, FieldMapping, InputFormat, OutputFormat, Theme};
+use crate::group::LineGrouping;
 use crate::message::{Message, Severity};
-use crate::utils::is_stdout_tty;
+use crate::signal::ForwardSignals;
+use crate::time::{parse_time, TimeRange};
+use crate::utils::{is_stdout_tty, parse_duration, terminal_width};
 
 #[derive(Debug, StructOpt)]
 struct Options {
-    #[structopt(short = "i", long = "input", use_delimiter = true, possible_values = format::get_input_format_variants(), default_value = format::get_input_format_default())]
+    /// Read settings from a TOML configuration file instead of the default locations
+    #[structopt(long = "config")]
+    config: Option<PathBuf>,
+
+    /// Use a named profile from the configuration file
+    #[structopt(short = "p", long = "profile")]
+    profile: Option<String>,
+
+    /// Input formats to try in order; `outer>inner` parses the message text of the outer format with the inner format
+    #[structopt(short = "i", long = "input", use_delimiter = true, default_value = format::get_input_format_default())]
     input_format: Vec<String>,
 
     #[structopt(short = "o", long = "output", possible_values = format::get_output_format_variants(), default_value = format::get_output_format_default())]
     output_format: String,
 
+    /// Read from log files instead of standard input
+    #[structopt(short = "f", long = "file")]
+    files: Vec<PathBuf>,
+
+    /// Keep reading files as they grow, following truncation and rotation
+    #[structopt(short = "F", long = "follow")]
+    follow: bool,
+
+    /// Assume that files are sorted by time, and stop reading each one at the first message after `--until`
+    #[structopt(long = "sorted")]
+    sorted: bool,
+
+    /// Merge messages from several inputs in order of time
+    #[structopt(short = "m", long = "merge")]
+    merge: bool,
+
+    /// How long to wait for messages from other inputs when merging
+    #[structopt(long = "merge-window", default_value = "1s", parse(try_from_str = parse_duration))]
+    merge_window: Duration,
+
+    /// Run the command with its output on pseudo-terminals, so that it keeps its colours and line buffering: `split` (the default) uses one for each of stdout and stderr, and `shared` one for both, which keeps their order exact but cannot tell them apart
+    #[structopt(long = "pty", require_equals = true, possible_values = &["split", "shared"])]
+    pty: Option<Option<PtyMode>>,
+
+    /// Browse messages in an interactive full-screen pager
+    #[structopt(short = "P", long = "pager")]
+    pager: bool,
+
+    /// The most messages that the pager keeps, after which the oldest are dropped
+    #[structopt(long = "pager-limit", default_value = "100000")]
+    pager_limit: usize,
+
+    #[structopt(flatten)]
+    input_options: InputOptions,
+
+    #[structopt(flatten)]
+    filter_options: FilterOptions,
+
     #[structopt(flatten)]
     display_options: DisplayOptions,
 
+    /// Colours of the pretty output, from the configuration file
+    #[structopt(skip)]
+    theme: Theme,
+
     #[structopt(subcommand)]
     subcommand: Option<Subcommand>,
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub struct InputOptions {
+    /// JSON file with candidate keys for the message, severity, time and context fields
+    #[structopt(long = "field-mapping")]
+    field_mapping: Option<PathBuf>,
+
+    /// Keys to read the message text from
+    #[structopt(long = "message-field", use_delimiter = true)]
+    message_fields: Vec<String>,
+
+    /// Keys to read the severity from
+    #[structopt(long = "severity-field", use_delimiter = true)]
+    severity_fields: Vec<String>,
+
+    /// Keys to read the timestamp from
+    #[structopt(long = "time-field", use_delimiter = true)]
+    time_fields: Vec<String>,
+
+    /// Keys of objects to add to the message context
+    #[structopt(long = "context-field", use_delimiter = true)]
+    context_fields: Vec<String>,
+
+    /// Attach stack traces and other continuation lines to the preceding message
+    #[structopt(long = "multiline")]
+    multiline: bool,
+
+    /// Treat lines matching a regular expression as continuations of the preceding message (implies grouping, without the --multiline heuristics)
+    #[structopt(long = "continuation")]
+    continuation_patterns: Vec<String>,
+
+    /// Field mapping from the configuration file
+    #[structopt(skip)]
+    config_fields: Option<FieldMapping>,
+}
+
+impl InputOptions {
+    pub fn field_mapping(&self) -> Result<FieldMapping, String> {
+        let mut fields = match &self.field_mapping {
+            Some(path) => {
+                let file = File::open(path)
+                    .map_err(|err| format!("Could not open {}: {}", path.display(), err))?;
+                serde_json::from_reader(BufReader::new(file))
+                    .map_err(|err| format!("Invalid field mapping {}: {}", path.display(), err))?
+            }
+            None => self.config_fields.clone().unwrap_or_default(),
+        };
+
+        fn override_keys(keys: &mut Vec<String>, overrides: &[String]) {
+            if !overrides.is_empty() {
+                *keys = overrides.to_vec();
+            }
+        }
+
+        override_keys(&mut fields.message, &self.message_fields);
+        override_keys(&mut fields.severity, &self.severity_fields);
+        override_keys(&mut fields.time, &self.time_fields);
+        override_keys(&mut fields.context, &self.context_fields);
+
+        Ok(fields)
+    }
+
+    pub fn line_grouping(&self) -> Result<LineGrouping, String> {
+        let patterns = self
+            .continuation_patterns
+            .iter()
+            .map(|pattern| {
+                regex::Regex::new(pattern)
+                    .map_err(|err| format!("Invalid pattern {:?}: {}", pattern, err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(LineGrouping::new(self.multiline, patterns))
+    }
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct FilterOptions {
+    /// Only show messages with at least this severity
+    #[structopt(short = "l", long = "level")]
+    min_severity: Option<String>,
+
+    /// Only show messages whose text matches a regular expression
+    #[structopt(short = "g", long = "grep")]
+    patterns: Vec<String>,
+
+    /// Only show messages matching a filter expression, such as `service=api and status>=500`
+    #[structopt(short = "w", long = "where")]
+    expressions: Vec<String>,
+
+    /// Only show messages at or after a time, such as `2020-03-01T12:00:00Z`, `14:05` or `15m ago`
+    #[structopt(long = "since")]
+    since: Option<String>,
+
+    /// Only show messages at or before a time, such as `2020-03-01T12:00:00Z`, `14:05` or `15m ago`
+    #[structopt(long = "until")]
+    until: Option<String>,
+
+    /// Collapse runs of repeated messages into the first one, with a repeat count
+    #[structopt(long = "collapse")]
+    collapse: bool,
+
+    /// Also collapse repeats within this time of the first message when other messages come in between
+    #[structopt(long = "collapse-window", parse(try_from_str = parse_duration))]
+    collapse_window: Option<Duration>,
+
+    /// What to mask when comparing messages to collapse: any of `uuid`, `hex` and `number`, or `none`
+    #[structopt(
+        long = "collapse-mask",
+        use_delimiter = true,
+        default_value = "uuid,hex,number"
+    )]
+    collapse_masks: Vec<String>,
+
+    /// Also mask matches of a regular expression when comparing messages to collapse
+    #[structopt(long = "collapse-pattern")]
+    collapse_patterns: Vec<String>,
+}
+
+impl FilterOptions {
+    pub fn filter(&self) -> Result<Filter, String> {
+        let mut filters = Vec::new();
+
+        if let Some(severity) = &self.min_severity {
+            filters.push(Filter::MinSeverity(Severity::try_parse_str(severity)?));
+        }
+
+        for pattern in &self.patterns {
+            let regex = regex::Regex::new(pattern)
+                .map_err(|err| format!("Invalid pattern {:?}: {}", pattern, err))?;
+            filters.push(Filter::Text(regex));
+        }
+
+        for expr in &self.expressions {
+            filters.push(Filter::parse(expr)?);
+        }
+
+        Ok(Filter::All(filters))
+    }
+
+    pub fn time_range(&self) -> Result<TimeRange, String> {
+        let now = chrono::Local::now();
+        Ok(TimeRange {
+            since: self
+                .since
+                .as_deref()
+                .map(|s| parse_time(s, now))
+                .transpose()?,
+            until: self
+                .until
+                .as_deref()
+                .map(|s| parse_time(s, now))
+                .transpose()?,
+        })
+    }
+
+    pub fn collapser(&self) -> Result<Option<Collapser>, String> {
+        if !self.collapse && self.collapse_window.is_none() {
+            return Ok(None);
+        }
+
+        let masks = self
+            .collapse_masks
+            .iter()
+            .filter(|mask| *mask != "none")
+            .map(|mask| mask.parse())
+            .collect::<Result<Vec<Mask>, _>>()?;
+
+        let patterns = self
+            .collapse_patterns
+            .iter()
+            .map(|pattern| {
+                regex::Regex::new(pattern)
+                    .map_err(|err| format!("Invalid pattern {:?}: {}", pattern, err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(Collapser::new(
+            Normalizer::new(masks, patterns),
+            self.collapse_window,
+        )))
+    }
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub struct DisplayOptions {
     #[structopt(short = "x", long = "context")]
@@ -37,6 +296,169 @@ pub struct DisplayOptions {
 
     #[structopt(short = "c", long = "compact")]
     compact: bool,
+
+    /// Pretty-print JSON objects and arrays embedded in message text
+    #[structopt(short = "j", long = "expand-json")]
+    expand_json: bool,
+
+    /// How to show the context: `aligned` key/value lines, `inline` on one line, or `json`
+    #[structopt(long = "context-layout", default_value = "aligned", possible_values = &["aligned", "inline", "json"])]
+    context_layout: ContextLayout,
+
+    /// Context keys to show first, in this order
+    #[structopt(long = "context-order", use_delimiter = true)]
+    context_order: Vec<String>,
+
+    /// Only show these context keys (and keys nested in them)
+    #[structopt(long = "include-context", use_delimiter = true)]
+    include_context: Vec<String>,
+
+    /// Do not show these context keys (and keys nested in them)
+    #[structopt(long = "exclude-context", use_delimiter = true)]
+    exclude_context: Vec<String>,
+
+    /// Truncate context values longer than this many characters
+    #[structopt(long = "context-width")]
+    context_width: Option<usize>,
+
+    /// When to use colours, where `auto` uses them on a terminal and honours `NO_COLOR` and `CLICOLOR_FORCE`
+    #[structopt(long = "color", default_value = "auto", possible_values = &["auto", "always", "never"])]
+    color: ColorChoice,
+
+    /// Wrap message text to fit this many columns, or 0 to not wrap [default: terminal width]
+    #[structopt(long = "width")]
+    width: Option<usize>,
+
+    /// How to display timestamps: `iso`, `time`, `relative` (to the previous message), `elapsed` (since the first message) or a strftime format
+    #[structopt(long = "time-format")]
+    time_format: Option<String>,
+
+    /// Display timestamps in UTC instead of local time
+    #[structopt(long = "utc")]
+    utc: bool,
+
+    /// Template for the `template` output format, such as `{time:%H:%M:%S} {level:5} {msg}`
+    #[structopt(long = "template")]
+    template: Option<String>,
+
+    /// Context keys to count the most frequent values of in the `stats` output format
+    #[structopt(long = "stats-keys", use_delimiter = true)]
+    stats_keys: Vec<String>,
+
+    /// How many of the most frequent values and messages to list in the `stats` output format
+    #[structopt(long = "stats-top", default_value = "10")]
+    stats_top: usize,
+
+    /// Time span of each bar of the `stats` histogram, which is made longer if it would give too many bars [default: chosen from the time range]
+    #[structopt(long = "stats-bucket", parse(try_from_str = parse_duration))]
+    stats_bucket: Option<Duration>,
+
+    /// How to print the `stats` report: as a `table` or as `json`
+    #[structopt(long = "stats-layout", default_value = "table", possible_values = &["table", "json"])]
+    stats_layout: StatsLayout,
+
+    /// Print the `stats` report this often while reading, and not only at the end [default: 2s when following files]
+    #[structopt(long = "stats-interval", parse(try_from_str = parse_duration))]
+    stats_interval: Option<Duration>,
+
+    /// Whether the periodic `stats` reports are redrawn in place, when writing to a terminal
+    #[structopt(skip)]
+    redraw: bool,
+}
+
+impl DisplayOptions {
+    fn use_color(&self) -> bool {
+        let is_set =
+            |name: &str| env::var_os(name).is_some_and(|value| !value.is_empty() && value != "0");
+
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto if is_set("NO_COLOR") => false,
+            ColorChoice::Auto if is_set("CLICOLOR_FORCE") => true,
+            ColorChoice::Auto => is_stdout_tty(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextLayout {
+    Aligned,
+    Inline,
+    Json,
+}
+
+impl FromStr for ContextLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ContextLayout, String> {
+        match s {
+            "aligned" => Ok(ContextLayout::Aligned),
+            "inline" => Ok(ContextLayout::Inline),
+            "json" => Ok(ContextLayout::Json),
+            _ => Err(format!("Invalid context layout: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsLayout {
+    Table,
+    Json,
+}
+
+impl FromStr for StatsLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StatsLayout, String> {
+        match s {
+            "table" => Ok(StatsLayout::Table),
+            "json" => Ok(StatsLayout::Json),
+            _ => Err(format!("Invalid stats layout: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PtyMode {
+    #[default]
+    Split,
+    Shared,
+}
+
+impl FromStr for PtyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PtyMode, String> {
+        match s {
+            "split" => Ok(PtyMode::Split),
+            "shared" => Ok(PtyMode::Shared),
+            _ => Err(format!("Invalid pseudo-terminal mode: {}", s)),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorChoice, String> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Invalid color choice: {}", s)),
+        }
+    }
 }
 
 #[derive(Debug, StructOpt)]
@@ -45,90 +467,606 @@ enum Subcommand {
     External(Vec<String>),
 }
 
+/// The stages that each line of input passes through on its way to the output.
+struct Pipeline<I, O> {
+    input: I,
+    grouping: LineGrouping,
+    filter: Filter,
+    time_range: TimeRange,
+    collapse: Option<Collapser>,
+    output: O,
+}
+
+impl<I: InputFormat, O: OutputFormat> Pipeline<I, O> {
+    fn parse(&self, line: &str, source: &Source) -> Message {
+        let mut message = self
+            .input
+            .parse_message(line, source.default_severity)
+            .unwrap_or_else(|| Message::from_text(line, source.default_severity))
+            .trim();
+
+        // the path does not replace a field of the same name in the message,
+        // such as the caller that logrus writes, but is prefixed instead
+        if let Some(file) = &source.file {
+            let mut key = "file".to_string();
+            while message.context.contains_key(&key) {
+                key.insert(0, '_');
+            }
+            message.add_context(key, file.as_str());
+        }
+        message.source = source.label.clone();
+
+        message
+    }
+
+    fn emit(&self, writer: &Mutex<impl Write>, message: Message) -> io::Result<()> {
+        if !self.filter.matches(&message) {
+            return Ok(());
+        }
+
+        match &self.collapse {
+            Some(collapse) => collapse.push(message, |message| self.print(writer, &message)),
+            None => self.print(writer, &message),
+        }
+    }
+
+    fn print(&self, writer: &Mutex<impl Write>, message: &Message) -> io::Result<()> {
+        let writer = &mut *writer.lock().unwrap();
+        self.output.print_message(writer, message)
+    }
+
+    /// Print what is due while waiting for input: runs of repeated messages
+    /// that have gone idle, and periodic output.
+    fn tick(&self, writer: &Mutex<impl Write>) -> io::Result<()> {
+        if let Some(collapse) = &self.collapse {
+            collapse.flush_idle(|message| self.print(writer, &message))?;
+        }
+
+        self.output.tick(&mut *writer.lock().unwrap())
+    }
+
+    /// Print the messages that are still held back at the end of the input.
+    fn finish(&self, writer: &Mutex<impl Write>) -> io::Result<()> {
+        if let Some(collapse) = &self.collapse {
+            collapse.flush(|message| self.print(writer, &message))?;
+        }
+
+        self.output.finish(&mut *writer.lock().unwrap())
+    }
+}
+
+/// Where a stream of input lines comes from.
+struct Source {
+    file: Option<String>,
+    label: Option<String>,
+    default_severity: Severity,
+
+    /// Whether the source is sorted by time and complete, so that reading can
+    /// stop at the end of the time range.
+    sorted: bool,
+}
+
+impl Source {
+    fn stream(default_severity: Severity) -> Source {
+        Source {
+            file: None,
+            label: None,
+            default_severity,
+            sorted: false,
+        }
+    }
+
+    fn file(path: &Path) -> Source {
+        Source {
+            file: Some(path.display().to_string()),
+            label: None,
+            default_severity: Severity::Default,
+            sorted: false,
+        }
+    }
+
+    fn sorted(self) -> Source {
+        Source {
+            sorted: true,
+            ..self
+        }
+    }
+
+    fn with_label(self, label: impl Into<String>) -> Source {
+        Source {
+            label: Some(label.into()),
+            ..self
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let opts = Options::from_args();
+    let matches = Options::clap().get_matches();
+    let mut opts = Options::from_clap(&matches);
+
+    if opts.pty.is_some() && opts.subcommand.is_none() {
+        return Err("--pty needs a command to run".into());
+    }
+
+    let config = Config::load(opts.config.as_deref())?;
+    config
+        .profile(opts.profile.as_deref())?
+        .apply(&mut opts, &matches)?;
 
-    let writer = io::stdout();
-    let style: AnyStyle = if is_stdout_tty() {
-        ColoredStyle.into()
+    if opts.display_options.width.is_none() && is_stdout_tty() {
+        opts.display_options.width = terminal_width();
+    }
+    if opts.display_options.stats_interval.is_none() && opts.follow {
+        opts.display_options.stats_interval = Some(Duration::from_secs(2));
+    }
+    opts.display_options.redraw = is_stdout_tty();
+
+    let fields = opts.input_options.field_mapping()?;
+    let input = format::get_input_format(&opts.input_format, &fields)?;
+    let grouping = opts.input_options.line_grouping()?;
+    let filter = opts.filter_options.filter()?;
+    let time_range = opts.filter_options.time_range()?;
+    let collapse = opts.filter_options.collapser()?;
+
+    if opts.pager {
+        if !is_stdout_tty() {
+            return Err("The pager needs standard output to be a terminal".into());
+        }
+
+        let (output_format, display_options, theme) = (
+            opts.output_format.clone(),
+            opts.display_options.clone(),
+            opts.theme.clone(),
+        );
+        let use_color = display_options.use_color();
+        let style = move || -> AnyStyle {
+            if use_color {
+                ColoredStyle::new(theme.clone()).into()
+            } else {
+                PlainStyle.into()
+            }
+        };
+        let pager = pager::Pager::new(
+            Box::new({
+                let (fields, style) = (fields.clone(), style.clone());
+                move |width| {
+                    let display_options = DisplayOptions {
+                        width: Some(width),
+                        redraw: false,
+                        ..display_options.clone()
+                    };
+                    format::get_output_format(&output_format, style(), &fields, &display_options)
+                }
+            }),
+            style(),
+            opts.pager_limit,
+        )?;
+
+        let (sender, messages) = channel::unbounded();
+        let (done_sender, done) = channel::bounded(1);
+        let pipeline = Pipeline {
+            input,
+            grouping,
+            filter,
+            time_range,
+            collapse,
+            output: pager::PagerSink(sender),
+        };
+
+        // input that is still being read is abandoned when the pager quits
+        thread::spawn(move || {
+            let result = run_input(&opts, &Mutex::new(io::sink()), &pipeline);
+            let _ = done_sender.send(result);
+        });
+
+        let status = pager.run(messages, done.clone())?;
+
+        // a command that is still running when the pager quits is stopped,
+        // rather than left behind with nothing reading its output
+        signal::terminate_child();
+        let _ = done.recv_timeout(Duration::from_secs(1));
+        exit_with(status);
+        return Ok(());
+    }
+
+    let style: AnyStyle = if opts.display_options.use_color() {
+        ColoredStyle::new(opts.theme.clone()).into()
     } else {
         PlainStyle.into()
     };
 
-    let input_format = format::get_input_format(&opts.input_format)?;
+    let pipeline = Pipeline {
+        input,
+        grouping,
+        filter,
+        time_range,
+        collapse,
+        output: format::get_output_format(
+            &opts.output_format,
+            style,
+            &fields,
+            &opts.display_options,
+        )?,
+    };
 
-    let output_format =
-        format::get_output_format(&opts.output_format, style, &opts.display_options)?;
+    let status = run_input(&opts, &Mutex::new(io::stdout()), &pipeline)?;
+    io::stdout().flush()?;
+    exit_with(status);
 
-    match opts.subcommand.as_ref() {
-        Some(Subcommand::External(args)) => {
-            run_command(writer, input_format, output_format, args)?;
-        }
-        None => {
-            run(
+    Ok(())
+}
+
+/// Exit with the status of a command that failed, as a shell would: with its
+/// exit code, or 128 plus the signal that terminated it.
+fn exit_with(status: Option<ExitStatus>) {
+    if let Some(status) = status.filter(|status| !status.success()) {
+        let code = status
+            .code()
+            .or_else(|| status.signal().map(|signal| 128 + signal))
+            .unwrap_or(1);
+        process::exit(code);
+    }
+}
+
+/// Read the input given by the options: the output of a command, files or
+/// standard input. Returns the exit status of the command, if any.
+fn run_input<I: InputFormat, O: OutputFormat>(
+    opts: &Options,
+    writer: &Mutex<impl Write + Send>,
+    pipeline: &Pipeline<I, O>,
+) -> io::Result<Option<ExitStatus>> {
+    let merge_window = if opts.merge {
+        Some(opts.merge_window)
+    } else {
+        None
+    };
+
+    let status = scope(|s| {
+        // print collapsed messages and periodic reports while waiting for input
+        let (stop, stopped) = channel::bounded::<()>(0);
+        let ticker = s.spawn(move |_| {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(GROUP_TIMEOUT) {
+                pipeline.tick(writer)?;
+            }
+            io::Result::Ok(())
+        });
+
+        let result = match opts.subcommand.as_ref() {
+            Some(Subcommand::External(args)) => {
+                let pty = opts.pty.map(Option::unwrap_or_default);
+
+                // the pager reads keys from the terminal, so the command must not
+                let stdin = if opts.pager {
+                    Stdio::null()
+                } else {
+                    Stdio::inherit()
+                };
+                run_command(writer, pipeline, args, stdin, pty, merge_window).map(Some)
+            }
+            None if !opts.files.is_empty() => {
+                let (follow, sorted) = (opts.follow, opts.sorted);
+                run_files(writer, pipeline, &opts.files, follow, sorted, merge_window).map(|_| None)
+            }
+            None => run(
                 io::stdin(),
-                &Mutex::new(writer),
-                input_format,
-                output_format,
-                Severity::Default,
-            )?;
+                pipeline,
+                &Source::stream(Severity::Default),
+                |message| pipeline.emit(writer, message),
+            )
+            .map(|_| None),
+        };
+
+        drop(stop);
+        ticker.join().unwrap()?;
+        result
+    })
+    .unwrap()?;
+
+    pipeline.finish(writer)?;
+    writer.lock().unwrap().flush()?;
+    Ok(status)
+}
+
+/// How long to wait for continuation lines before printing a message.
+const GROUP_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn run<I: InputFormat, O: OutputFormat>(
+    reader: impl Read + Send + 'static,
+    pipeline: &Pipeline<I, O>,
+    source: &Source,
+    mut emit: impl FnMut(Message) -> io::Result<()>,
+) -> io::Result<()> {
+    let lines = read_lines(reader);
+
+    let mut group = pipeline.grouping.start();
+    let mut pending: Option<Message> = None;
+
+    // partial lines waiting to be continued, for each stream of the input
+    let mut partials: Vec<(Option<String>, String)> = Vec::new();
+
+    // messages without a timestamp are assumed to be from the time of the
+    // previous message when checking the time range
+    let mut last_time = None;
+    let mut emit = |message: Message| -> io::Result<bool> {
+        if let Some(time) = message.time.or(last_time) {
+            last_time = Some(time);
+
+            if source.sorted && pipeline.time_range.is_past(time) {
+                return Ok(false);
+            } else if !pipeline.time_range.contains(time) {
+                return Ok(true);
+            }
+        }
+
+        emit(message)?;
+        Ok(true)
+    };
+
+    loop {
+        // without grouping, each line is printed as soon as it is read
+        let received = if pipeline.grouping.is_enabled() {
+            lines.recv_timeout(GROUP_TIMEOUT)
+        } else {
+            lines.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        };
+
+        let line = match received {
+            Ok(line) => line?,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(message) = pending.take() {
+                    if !emit(message)? {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        // join lines that the input format split into partial lines
+        let stream = pipeline.input.stream(&line);
+        let mut complete = Vec::with_capacity(2);
+        match partials.iter().position(|(other, _)| *other == stream) {
+            Some(index) => {
+                let (_, partial) = partials.remove(index);
+                match pipeline.input.join_partial(&partial, &line) {
+                    Some(joined) => complete.push(joined),
+                    None => complete.extend([partial, line]),
+                }
+            }
+            None => complete.push(line),
+        }
+        if complete
+            .last()
+            .is_some_and(|line| pipeline.input.is_partial(line))
+        {
+            partials.push((stream, complete.pop().unwrap()));
+        }
+
+        for line in complete {
+            // continuation lines are recognised by the text inside any
+            // envelope, such as that of container logs
+            let payload = pipeline.input.payload(&line);
+            let text = payload.as_deref().unwrap_or(&line);
+
+            let is_continuation = group.is_continuation(text);
+            match pending.as_mut() {
+                Some(message) if is_continuation => message.push_line(text),
+                _ => {
+                    let message = pipeline.parse(&line, source);
+                    if let Some(message) = pending.take() {
+                        if !emit(message)? {
+                            return Ok(());
+                        }
+                    }
+
+                    if pipeline.grouping.is_enabled() {
+                        pending = Some(message);
+                    } else if !emit(message)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(message) = pending {
+        if !emit(message)? {
+            return Ok(());
         }
     }
 
+    // partial lines at the end of the input are never completed
+    for (_, line) in partials {
+        emit(pipeline.parse(&line, source))?;
+    }
+
     Ok(())
 }
 
-fn run(
-    reader: impl Read,
-    writer: &Mutex<impl Write>,
-    input: impl InputFormat,
-    output: impl OutputFormat,
-    default_severity: Severity,
+/// Read lines on a background thread, so that the reader can wait for
+/// continuation lines without blocking on input.
+fn read_lines(reader: impl Read + Send + 'static) -> Receiver<io::Result<String>> {
+    let (sender, receiver) = channel::bounded(1024);
+
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+
+        loop {
+            // invalid UTF-8 (such as binary fields in journal exports) is
+            // replaced rather than ending the input
+            let mut line = Vec::new();
+            let result = match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&line);
+                    Ok(line.trim_end_matches('\n').to_string())
+                }
+                Err(err) => Err(err),
+            };
+
+            let is_err = result.is_err();
+            if sender.send(result).is_err() || is_err {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn run_files<I: InputFormat, O: OutputFormat>(
+    writer: &Mutex<impl Write + Send>,
+    pipeline: &Pipeline<I, O>,
+    paths: &[PathBuf],
+    follow: bool,
+    sorted: bool,
+    merge_window: Option<Duration>,
 ) -> io::Result<()> {
-    let mut reader = BufReader::new(reader);
-
-    let mut line = String::new();
-    while {
-        line.clear();
-        reader.read_line(&mut line)? > 0
-    } {
-        let line = line.trim_end_matches('\n');
-
-        let message = input
-            .parse_message(line, default_severity)
-            .unwrap_or_else(|| Message::from_text(line, default_severity))
-            .trim();
+    let source = |path: &PathBuf| {
+        let mut source = Source::file(path);
+        if sorted && !follow {
+            source = source.sorted();
+        }
 
-        let writer = &mut *writer.lock().unwrap();
-        output.print_message(writer, &message)?;
+        match path.file_name() {
+            Some(name) if paths.len() > 1 => source.with_label(name.to_string_lossy()),
+            _ => source,
+        }
+    };
+
+    if !follow && merge_window.is_none() {
+        for path in paths {
+            run(
+                source::open(path, false)?,
+                pipeline,
+                &source(path),
+                |message| pipeline.emit(writer, message),
+            )?;
+        }
+        return Ok(());
     }
 
-    Ok(())
+    let sources = paths
+        .iter()
+        .map(|path| Ok((source::open(path, follow)?, source(path))))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    run_concurrently(writer, pipeline, sources, merge_window)
 }
 
-fn run_command(
-    writer: impl Write + Send + Sync,
-    input: impl InputFormat,
-    output: impl OutputFormat,
+fn run_command<I: InputFormat, O: OutputFormat>(
+    writer: &Mutex<impl Write + Send>,
+    pipeline: &Pipeline<I, O>,
     command: &[impl AsRef<OsStr>],
+    stdin: Stdio,
+    pty: Option<PtyMode>,
+    merge_window: Option<Duration>,
 ) -> io::Result<ExitStatus> {
-    let mut child = Command::new(&command[0])
-        .args(&command[1..])
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
+    let mut command_builder = Command::new(&command[0]);
+    command_builder.args(&command[1..]).stdin(stdin);
+
+    let mut readers: Vec<Box<dyn Read + Send>> = Vec::new();
+    match pty {
+        Some(PtyMode::Split) => {
+            let (stdout, stdout_pty) = pty::open()?;
+            let (stderr, stderr_pty) = pty::open()?;
+            command_builder.stdout(stdout_pty).stderr(stderr_pty);
+            readers.push(Box::new(stdout));
+            readers.push(Box::new(stderr));
+        }
+        Some(PtyMode::Shared) => {
+            let (output, output_pty) = pty::open()?;
+            command_builder
+                .stdout(output_pty.try_clone()?)
+                .stderr(output_pty);
+            readers.push(Box::new(output));
+        }
+        None => {
+            command_builder
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+        }
+    }
 
-    let stdout = child.stdout.take().expect("take stdout");
-    let stderr = child.stderr.take().expect("take stderr");
+    let mut child = command_builder.spawn()?;
+    let _forward = ForwardSignals::to(child.id())?;
 
-    let writer = Mutex::new(writer);
+    // the copies of the pseudo-terminals held for the command must be closed
+    // for the end of its output to be seen
+    drop(command_builder);
 
+    if pty.is_none() {
+        readers.push(Box::new(child.stdout.take().expect("take stdout")));
+        readers.push(Box::new(child.stderr.take().expect("take stderr")));
+    }
+
+    let sources = if pty == Some(PtyMode::Shared) {
+        // stdout and stderr cannot be told apart
+        vec![Source::stream(Severity::Default)]
+    } else if merge_window.is_some() {
+        vec![
+            Source::stream(Severity::Info).with_label("stdout"),
+            Source::stream(Severity::Error).with_label("stderr"),
+        ]
+    } else {
+        vec![
+            Source::stream(Severity::Info),
+            Source::stream(Severity::Error),
+        ]
+    };
+    let sources = readers.into_iter().zip(sources).collect();
+
+    if let Err(err) = run_concurrently(writer, pipeline, sources, merge_window) {
+        // the command is not left running when its output can no longer be
+        // read or printed
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(err);
+    }
+    child.wait()
+}
+
+/// Read from several sources at once, printing messages either as they arrive
+/// or, if a merge window is given, merged in order of time.
+fn run_concurrently<I: InputFormat, O: OutputFormat>(
+    writer: &Mutex<impl Write + Send>,
+    pipeline: &Pipeline<I, O>,
+    sources: Vec<(Box<dyn Read + Send>, Source)>,
+    merge_window: Option<Duration>,
+) -> io::Result<()> {
     scope(|s| {
-        s.spawn(|_| run(stdout, &writer, &input, &output, Severity::Info));
-        s.spawn(|_| run(stderr, &writer, &input, &output, Severity::Error));
-        child.wait()
+        let mut threads = Vec::new();
+        let mut receivers = Vec::new();
+
+        for (reader, source) in sources {
+            if merge_window.is_some() {
+                let (sender, receiver) = channel::bounded(1024);
+                receivers.push(receiver);
+
+                threads.push(s.spawn(move |_| {
+                    run(reader, pipeline, &source, |message| {
+                        sender
+                            .send(message)
+                            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "merge stopped"))
+                    })
+                }));
+            } else {
+                threads.push(s.spawn(move |_| {
+                    run(reader, pipeline, &source, |message| {
+                        pipeline.emit(writer, message)
+                    })
+                }));
+            }
+        }
+
+        if let Some(window) = merge_window {
+            merge::merge(receivers, window, |message| pipeline.emit(writer, message))?;
+        }
+
+        threads
+            .into_iter()
+            .try_for_each(|thread| thread.join().unwrap())
     })
     .unwrap()
-    .map_err(|err| err.into())
 }
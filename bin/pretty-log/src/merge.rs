@@ -0,0 +1,127 @@
+use chrono::{DateTime, Local};
+use crossbeam::channel::{Receiver, Select, TryRecvError};
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::message::Message;
+
+/// The next message of a source, waiting to be merged.
+struct Head {
+    message: Message,
+    time: DateTime<Local>,
+    received: Instant,
+}
+
+/// Merge messages from several sources, ordered by time.
+///
+/// Each source is expected to be (roughly) ordered by time already. A message
+/// is printed once every source has a later message waiting, or once it has
+/// waited for `window`, whichever comes first. Messages without a timestamp
+/// are ordered by the time of the previous message from the same source, or by
+/// the time they were received.
+pub fn merge(
+    sources: Vec<Receiver<Message>>,
+    window: Duration,
+    mut emit: impl FnMut(Message) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut heads: Vec<Option<Head>> = sources.iter().map(|_| None).collect();
+    let mut last_times: Vec<Option<DateTime<Local>>> = sources.iter().map(|_| None).collect();
+    let mut open: Vec<bool> = sources.iter().map(|_| true).collect();
+
+    loop {
+        for (index, source) in sources.iter().enumerate() {
+            if heads[index].is_some() || !open[index] {
+                continue;
+            }
+
+            match source.try_recv() {
+                Ok(message) => {
+                    let time = message
+                        .time
+                        .or(last_times[index])
+                        .unwrap_or_else(Local::now);
+                    last_times[index] = Some(time);
+
+                    heads[index] = Some(Head {
+                        message,
+                        time,
+                        received: Instant::now(),
+                    });
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => open[index] = false,
+            }
+        }
+
+        let next = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(index, head)| head.as_ref().map(|head| (index, head)))
+            .min_by_key(|(index, head)| (head.time, *index))
+            .map(|(index, head)| (index, head.received.elapsed()));
+
+        let waiting: Vec<usize> = (0..sources.len())
+            .filter(|&index| open[index] && heads[index].is_none())
+            .collect();
+
+        let timeout = match next {
+            Some((index, _)) if waiting.is_empty() => {
+                emit(heads[index].take().unwrap().message)?;
+                continue;
+            }
+            Some((index, elapsed)) if elapsed >= window => {
+                emit(heads[index].take().unwrap().message)?;
+                continue;
+            }
+            Some((_, elapsed)) => window - elapsed,
+            None if waiting.is_empty() => return Ok(()),
+            None => window,
+        };
+
+        let mut select = Select::new();
+        for &index in &waiting {
+            select.recv(&sources[index]);
+        }
+        let _ = select.ready_timeout(timeout);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use crossbeam::channel;
+
+    fn message(text: &str, seconds: Option<i64>) -> Message {
+        let mut message = Message::from_text(text, Default::default());
+        message.time = seconds.map(|seconds| Local.timestamp_opt(seconds, 0).unwrap());
+        message
+    }
+
+    #[test]
+    fn merge_by_time() {
+        let (a, a_receiver) = channel::unbounded();
+        let (b, b_receiver) = channel::unbounded();
+
+        a.send(message("a1", Some(1))).unwrap();
+        a.send(message("a3", Some(3))).unwrap();
+        a.send(message("a-untimed", None)).unwrap();
+        a.send(message("a5", Some(5))).unwrap();
+        b.send(message("b2", Some(2))).unwrap();
+        b.send(message("b4", Some(4))).unwrap();
+        drop((a, b));
+
+        let mut texts = Vec::new();
+        merge(
+            vec![a_receiver, b_receiver],
+            Duration::from_secs(1),
+            |message| {
+                texts.push(message.text);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(texts, vec!["a1", "b2", "a3", "a-untimed", "b4", "a5"]);
+    }
+}
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Local};
 use serde::{de::Deserializer, Deserialize};
+pub use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::{self, Display};
 
@@ -10,7 +11,24 @@ pub struct Message {
     pub text: String,
     pub severity: Severity,
     pub time: Option<DateTime<Local>>,
-    pub context: HashMap<String, String>,
+    pub context: HashMap<String, Value>,
+
+    /// Label of the input the message was read from, when reading from
+    /// several inputs at once.
+    pub source: Option<String>,
+
+    /// Set when the message stands for several repeated messages.
+    pub repeat: Option<Repeat>,
+}
+
+/// A run of repeated messages that was collapsed into its first message.
+#[derive(Clone, Debug)]
+pub struct Repeat {
+    /// The number of messages, including the first one.
+    pub count: usize,
+
+    /// The time of the last message.
+    pub last_time: Option<DateTime<Local>>,
 }
 
 impl Message {
@@ -20,18 +38,48 @@ impl Message {
             severity,
             time: None,
             context: HashMap::new(),
+            source: None,
+            repeat: None,
         }
     }
 
+    /// Combine the message with one parsed from its own text, where the fields
+    /// of the inner message take precedence.
     pub fn merge_with(&mut self, message: Message) {
         self.text = message.text;
         self.severity = message.severity.or(self.severity);
         self.time = message.time.or(self.time);
+        self.context.extend(message.context);
+    }
+
+    pub fn add_context(&mut self, name: impl AsRef<str>, value: impl Into<Value>) {
+        self.context.insert(name.as_ref().to_string(), value.into());
+    }
+
+    /// Look up a context key, treating dots as paths into nested objects when
+    /// the full key is not present.
+    pub fn lookup(&self, key: &str) -> Option<&Value> {
+        if let Some(value) = self.context.get(key) {
+            return Some(value);
+        }
+
+        let mut parts = key.split('.');
+        let mut value = self.context.get(parts.next()?)?;
+        for part in parts {
+            value = match value {
+                Value::Object(object) => object.get(part)?,
+                Value::Array(array) => array.get(part.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
     }
 
-    pub fn add_context(&mut self, name: impl AsRef<str>, value: impl AsRef<str>) {
-        self.context
-            .insert(name.as_ref().to_string(), value.as_ref().to_string());
+    /// Append a continuation line to the message text.
+    pub fn push_line(&mut self, line: &str) {
+        self.text.push('\n');
+        self.text.push_str(line);
     }
 
     pub fn trim(mut self) -> Message {
@@ -40,8 +88,9 @@ impl Message {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Severity {
+    #[default]
     Default,
     Debug,
     Info,
@@ -53,12 +102,6 @@ pub enum Severity {
     Emergency,
 }
 
-impl Default for Severity {
-    fn default() -> Severity {
-        Severity::Default
-    }
-}
-
 impl Display for Severity {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "{:?}", self)
@@ -81,38 +124,52 @@ impl Severity {
 
         let severity = severity.to_lowercase();
         match severity.as_str() {
-            "emergency" => Ok(Severity::Emergency),
+            "emergency" | "emerg" => Ok(Severity::Emergency),
             "alert" => Ok(Severity::Alert),
-            "critical" => Ok(Severity::Critical),
-            "error" => Ok(Severity::Error),
-            "warning" => Ok(Severity::Warning),
+            "critical" | "crit" | "fatal" | "panic" => Ok(Severity::Critical),
+            "error" | "err" => Ok(Severity::Error),
+            "warning" | "warn" => Ok(Severity::Warning),
             "notice" => Ok(Severity::Notice),
-            "info" => Ok(Severity::Info),
-            "debug" => Ok(Severity::Debug),
+            "info" | "information" => Ok(Severity::Info),
+            "debug" | "trace" => Ok(Severity::Debug),
             "default" => Ok(Severity::Default),
             _ => Err(format!("Unknown severity level: {}", severity)),
         }
     }
 
+    /// Convert a syslog severity, from emergency (0) to debug (7).
+    pub fn from_syslog(severity: u64) -> Severity {
+        match severity {
+            0 => Severity::Emergency,
+            1 => Severity::Alert,
+            2 => Severity::Critical,
+            3 => Severity::Error,
+            4 => Severity::Warning,
+            5 => Severity::Notice,
+            6 => Severity::Info,
+            _ => Severity::Debug,
+        }
+    }
+
+    /// Convert a numeric severity, where numbers from 10 are levels of bunyan
+    /// and pino, from trace (10) to fatal (60), and smaller numbers count up
+    /// from default (0) to emergency (8).
     pub fn parse_u64(severity: u64) -> Severity {
-        if severity >= (Severity::Emergency as u64) {
-            Severity::Emergency
-        } else if severity >= (Severity::Alert as u64) {
-            Severity::Alert
-        } else if severity >= (Severity::Critical as u64) {
-            Severity::Critical
-        } else if severity >= (Severity::Error as u64) {
-            Severity::Error
-        } else if severity >= (Severity::Warning as u64) {
-            Severity::Warning
-        } else if severity >= (Severity::Notice as u64) {
-            Severity::Notice
-        } else if severity >= (Severity::Info as u64) {
-            Severity::Info
-        } else if severity >= (Severity::Debug as u64) {
-            Severity::Debug
-        } else {
-            Severity::Default
+        match severity {
+            60.. => Severity::Critical,
+            50..=59 => Severity::Error,
+            40..=49 => Severity::Warning,
+            30..=39 => Severity::Info,
+            10..=29 => Severity::Debug,
+            8.. => Severity::Emergency,
+            7 => Severity::Alert,
+            6 => Severity::Critical,
+            5 => Severity::Error,
+            4 => Severity::Warning,
+            3 => Severity::Notice,
+            2 => Severity::Info,
+            1 => Severity::Debug,
+            0 => Severity::Default,
         }
     }
 }
@@ -124,7 +181,7 @@ impl<'de> Deserialize<'de> for Severity {
     {
         match StringOrNumber::<u64>::deserialize(deserializer)? {
             StringOrNumber::String(severity) => {
-                Ok(Severity::try_parse_str(&severity).unwrap_or_else(|_| Severity::Default))
+                Ok(Severity::try_parse_str(&severity).unwrap_or(Severity::Default))
             }
             StringOrNumber::Number(severity) => Ok(Severity::parse_u64(severity)),
         }
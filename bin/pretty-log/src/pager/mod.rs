@@ -0,0 +1,429 @@
+use crossbeam::channel::{Receiver, Sender};
+use crossterm::event::{self, Event, KeyEventKind};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use serde_json::Map;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::mem;
+use std::panic;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::format::style::{AnyStyle, Element, Style};
+use crate::format::{json_lines, DynOutputFormat, OutputFormat};
+use crate::message::{Message, Value};
+
+mod state;
+
+use self::state::PagerState;
+
+/// An output format that hands messages to the pager instead of printing them.
+pub struct PagerSink(pub Sender<Message>);
+
+impl OutputFormat for PagerSink {
+    fn print_message<W: Write + ?Sized>(
+        &self,
+        _writer: &mut W,
+        message: &Message,
+    ) -> io::Result<()> {
+        self.0
+            .send(message.clone())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "pager closed"))
+    }
+}
+
+/// Creates the output format used to render messages, for a given width.
+pub type MakeOutput = Box<dyn Fn(usize) -> Result<Box<dyn DynOutputFormat>, String>>;
+
+/// Columns to the left of each line, used to mark the selected message.
+const GUTTER_WIDTH: usize = 2;
+
+/// An interactive full-screen view of messages, which keeps the most recent
+/// messages so that the filters can be changed at any time.
+pub struct Pager {
+    make_output: MakeOutput,
+    output: Box<dyn DynOutputFormat>,
+    style: AnyStyle,
+    width: usize,
+    height: usize,
+
+    state: PagerState,
+
+    /// The lines of each message as printed by the output format, by number.
+    rendered: BTreeMap<usize, Vec<String>>,
+}
+
+/// The full-screen mode of the terminal, which is left again when dropped or
+/// on a panic, so that the terminal is usable after the pager fails.
+struct Screen(());
+
+impl Screen {
+    fn enter(stdout: &mut impl Write) -> io::Result<Screen> {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = restore_terminal();
+            default_hook(info);
+        }));
+
+        terminal::enable_raw_mode()?;
+        let screen = Screen(());
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(screen)
+    }
+
+    fn leave(self) -> io::Result<()> {
+        mem::forget(self);
+        restore_terminal()
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()
+}
+
+impl Pager {
+    /// Create a pager for the size of the terminal, which keeps at most
+    /// `limit` messages.
+    pub fn new(make_output: MakeOutput, style: AnyStyle, limit: usize) -> Result<Pager, String> {
+        let (width, height) = terminal::size().map_err(|err| err.to_string())?;
+        let width = width as usize;
+        let output = make_output(width.saturating_sub(GUTTER_WIDTH))?;
+
+        Ok(Pager {
+            make_output,
+            output,
+            style,
+            width,
+            height: height as usize,
+            state: PagerState::new(limit),
+            rendered: BTreeMap::new(),
+        })
+    }
+
+    /// Show messages as they arrive until the user quits.
+    ///
+    /// `done` receives the result of reading the input once it ends, and the
+    /// exit status of the command it was read from (if any) is returned.
+    pub fn run(
+        mut self,
+        messages: Receiver<Message>,
+        done: Receiver<io::Result<Option<ExitStatus>>>,
+    ) -> io::Result<Option<ExitStatus>> {
+        let mut stdout = io::stdout();
+        let screen = Screen::enter(&mut stdout)?;
+
+        let result = self.event_loop(&mut stdout, messages, done);
+
+        screen.leave()?;
+        result.map(|_| self.state.exit_status)
+    }
+
+    fn event_loop(
+        &mut self,
+        stdout: &mut impl Write,
+        messages: Receiver<Message>,
+        done: Receiver<io::Result<Option<ExitStatus>>>,
+    ) -> io::Result<()> {
+        let mut dirty = true;
+
+        loop {
+            // take messages in batches, so that the screen is still redrawn
+            // while reading fast input
+            for message in messages.try_iter().take(10_000) {
+                self.state.push(message);
+                dirty = true;
+            }
+            if dirty {
+                // forget the lines of messages that were dropped
+                self.rendered = self.rendered.split_off(&self.state.first_number());
+            }
+
+            if let Ok(result) = done.try_recv() {
+                self.state.finish(result);
+                dirty = true;
+            }
+
+            if dirty {
+                self.draw(stdout)?;
+                dirty = false;
+            }
+
+            if !event::poll(Duration::from_millis(50))? {
+                continue;
+            }
+
+            match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    let page = self.visible_messages();
+                    if !self.state.handle_key(key, page) {
+                        return Ok(());
+                    }
+                }
+                Event::Resize(width, height) => self.resize(width as usize, height as usize)?,
+                _ => continue,
+            }
+            dirty = true;
+        }
+    }
+
+    fn resize(&mut self, width: usize, height: usize) -> io::Result<()> {
+        self.width = width;
+        self.height = height;
+        self.output =
+            (self.make_output)(width.saturating_sub(GUTTER_WIDTH)).map_err(io::Error::other)?;
+        self.rendered.clear();
+        Ok(())
+    }
+
+    /// The lines of a message as printed by the output format.
+    fn lines(&mut self, number: usize) -> &[String] {
+        let (output, message) = (&self.output, self.state.message(number));
+        self.rendered.entry(number).or_insert_with(|| {
+            let mut buffer = Vec::new();
+            if output.print_message(&mut buffer, message).is_err() {
+                return vec![message.text.clone()];
+            }
+
+            String::from_utf8_lossy(&buffer)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        })
+    }
+
+    fn list_height(&self) -> usize {
+        let rows = self.height.saturating_sub(1);
+        if self.state.detail {
+            rows - (rows * 2 / 5).max(3).min(rows)
+        } else {
+            rows
+        }
+    }
+
+    /// The number of messages that fit on screen from the top.
+    fn visible_messages(&mut self) -> usize {
+        let height = self.list_height();
+        let mut rows = 0;
+        let mut count = 0;
+        for position in self.state.top..self.state.shown.len() {
+            rows += self.lines(self.state.shown[position]).len().max(1);
+            if rows > height {
+                break;
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Scroll so that the selected message is on screen.
+    fn scroll_to_selected(&mut self) {
+        let (selected, mut top) = (self.state.selected, self.state.top.min(self.state.selected));
+
+        let height = self.list_height();
+        while top < selected {
+            let rows: usize = (top..=selected)
+                .map(|position| self.lines(self.state.shown[position]).len().max(1))
+                .sum();
+            if rows <= height {
+                break;
+            }
+            top += 1;
+        }
+        self.state.top = top;
+    }
+
+    fn draw(&mut self, stdout: &mut impl Write) -> io::Result<()> {
+        self.scroll_to_selected();
+
+        let list_height = self.list_height();
+        let mut row = 0;
+        let mut position = self.state.top;
+        while row < list_height && position < self.state.shown.len() {
+            let is_selected = position == self.state.selected;
+            let lines = self.lines(self.state.shown[position]).to_vec();
+            for line in lines.iter().take(list_height - row) {
+                let gutter = if is_selected { "▌ " } else { "  " };
+                let line = truncate_styled(line, self.width.saturating_sub(GUTTER_WIDTH));
+                queue!(
+                    stdout,
+                    cursor::MoveTo(0, row as u16),
+                    terminal::Clear(ClearType::CurrentLine),
+                    SetAttribute(Attribute::Bold),
+                    Print(gutter),
+                    SetAttribute(Attribute::Reset),
+                    Print(line)
+                )?;
+                row += 1;
+            }
+            position += 1;
+        }
+
+        for row in row..list_height {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, row as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+        }
+
+        if self.state.detail {
+            self.draw_detail(stdout, list_height)?;
+        }
+
+        self.draw_status(stdout)?;
+        stdout.flush()
+    }
+
+    /// Draw all of the selected message below the list of messages.
+    fn draw_detail(&mut self, stdout: &mut impl Write, top: usize) -> io::Result<()> {
+        let rows = self.height.saturating_sub(1).saturating_sub(top);
+        let mut lines = Vec::new();
+
+        if let Some(message) = self.state.selected_message() {
+            let severity = message.severity;
+
+            let mut header = Vec::new();
+            self.style.element(&mut header, Element::Level, severity)?;
+            write!(header, "{}", severity.to_string().to_uppercase())?;
+            self.style.reset(&mut header)?;
+            if let Some(time) = message.time {
+                write!(header, "  {}", time.to_rfc3339())?;
+            }
+            if let Some(source) = &message.source {
+                write!(header, "  [{}]", source)?;
+            }
+            lines.push(String::from_utf8_lossy(&header).into_owned());
+            lines.extend(message.text.lines().map(str::to_string));
+
+            let context: Map<_, _> = message
+                .context
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            if !context.is_empty() {
+                for line in json_lines(&Value::Object(context)) {
+                    let mut buffer = Vec::new();
+                    for (element, text) in line {
+                        self.style.element(&mut buffer, element, severity)?;
+                        write!(buffer, "{}", text)?;
+                        self.style.reset(&mut buffer)?;
+                    }
+                    lines.push(String::from_utf8_lossy(&buffer).into_owned());
+                }
+            }
+        }
+
+        self.state.detail_offset = self
+            .state
+            .detail_offset
+            .min(lines.len().saturating_sub(rows.saturating_sub(1)));
+
+        let title = format!(
+            "{:─<width$}",
+            "── details (J/K to scroll) ",
+            width = self.width
+        );
+        queue!(
+            stdout,
+            cursor::MoveTo(0, top as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            SetAttribute(Attribute::Dim),
+            Print(truncate_styled(&title, self.width)),
+            SetAttribute(Attribute::Reset)
+        )?;
+
+        let mut lines = lines.iter().skip(self.state.detail_offset);
+        for row in top + 1..top + rows {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, row as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            if let Some(line) = lines.next() {
+                queue!(
+                    stdout,
+                    Print("  "),
+                    Print(truncate_styled(line, self.width.saturating_sub(2)))
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_status(&self, stdout: &mut impl Write) -> io::Result<()> {
+        let row = self.height.saturating_sub(1) as u16;
+        queue!(
+            stdout,
+            cursor::MoveTo(0, row),
+            terminal::Clear(ClearType::CurrentLine)
+        )?;
+
+        if let Some(line) = self.state.prompt_line() {
+            queue!(stdout, Print(truncate_styled(&line, self.width)))?;
+            return Ok(());
+        }
+
+        let line = format!("{:width$}", self.state.status_line(), width = self.width);
+        queue!(
+            stdout,
+            SetAttribute(Attribute::Reverse),
+            Print(truncate_styled(&line, self.width)),
+            SetAttribute(Attribute::Reset)
+        )
+    }
+}
+
+/// Cut a line with ANSI escape sequences to a number of visible characters,
+/// keeping all escape sequences so that styles are still reset.
+fn truncate_styled(line: &str, width: usize) -> String {
+    let mut truncated = String::with_capacity(line.len());
+    let mut visible = 0;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            truncated.push(ch);
+            if let Some(next) = chars.next() {
+                truncated.push(next);
+                if next == '[' {
+                    for ch in chars.by_ref() {
+                        truncated.push(ch);
+                        if ('@'..='~').contains(&ch) {
+                            break;
+                        }
+                    }
+                }
+            }
+        } else if visible < width {
+            truncated.push(ch);
+            visible += 1;
+        }
+    }
+
+    truncated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn truncate_lines() {
+        assert_eq!(truncate_styled("hello world", 5), "hello");
+        assert_eq!(
+            truncate_styled("\x1b[1mhello\x1b[0m world", 3),
+            "\x1b[1mhel\x1b[0m"
+        );
+        assert_eq!(truncate_styled("æøå", 5), "æøå");
+    }
+}
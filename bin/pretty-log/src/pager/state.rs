@@ -0,0 +1,538 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::io;
+use std::process::ExitStatus;
+
+use crate::filter::Filter;
+use crate::message::{Message, Severity, Value};
+
+/// The severities that can be hidden, in the order of the keys `0` to `8`.
+const SEVERITIES: [Severity; 9] = [
+    Severity::Default,
+    Severity::Debug,
+    Severity::Info,
+    Severity::Notice,
+    Severity::Warning,
+    Severity::Error,
+    Severity::Critical,
+    Severity::Alert,
+    Severity::Emergency,
+];
+
+const HELP: &str = "q quit  / search  n/N next/prev  w filter  0-8 levels  d details  F follow";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Prompt {
+    Search,
+    Filter,
+}
+
+/// What the pager shows, and how it changes with new messages and key presses,
+/// apart from drawing it on the terminal.
+///
+/// The most recent `limit` messages are kept, so that the filters can be
+/// changed at any time. Messages are numbered in the order they arrived,
+/// including those that were dropped.
+pub struct PagerState {
+    messages: VecDeque<Message>,
+    dropped: usize,
+    limit: usize,
+
+    /// Numbers of the messages that pass the filters.
+    pub shown: VecDeque<usize>,
+    hidden: Vec<Severity>,
+    filter: Filter,
+    filter_expr: String,
+    search: Option<Regex>,
+    search_expr: String,
+
+    /// Index into `shown` of the selected message and the first message on screen.
+    pub selected: usize,
+    pub top: usize,
+    follow: bool,
+    pub detail: bool,
+    pub detail_offset: usize,
+
+    /// The prompt being edited, with the value it had before and the message
+    /// that was selected when it was opened.
+    prompt: Option<(Prompt, String, usize)>,
+    status: Option<String>,
+    end_of_input: bool,
+
+    /// The exit status of the command that the input is read from.
+    pub exit_status: Option<ExitStatus>,
+}
+
+impl PagerState {
+    pub fn new(limit: usize) -> PagerState {
+        PagerState {
+            messages: VecDeque::new(),
+            dropped: 0,
+            limit: limit.max(1),
+            shown: VecDeque::new(),
+            hidden: Vec::new(),
+            filter: Filter::All(Vec::new()),
+            filter_expr: String::new(),
+            search: None,
+            search_expr: String::new(),
+            selected: 0,
+            top: 0,
+            follow: true,
+            detail: false,
+            detail_offset: 0,
+            prompt: None,
+            status: None,
+            end_of_input: false,
+            exit_status: None,
+        }
+    }
+
+    /// The message with the given number, which must not have been dropped.
+    pub fn message(&self, number: usize) -> &Message {
+        &self.messages[number - self.dropped]
+    }
+
+    /// The number of the oldest message that is kept.
+    pub fn first_number(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn selected_message(&self) -> Option<&Message> {
+        self.shown
+            .get(self.selected)
+            .map(|&number| self.message(number))
+    }
+
+    pub fn push(&mut self, message: Message) {
+        if self.messages.len() == self.limit {
+            self.messages.pop_front();
+            if self.shown.front() == Some(&self.dropped) {
+                self.shown.pop_front();
+                self.selected = self.selected.saturating_sub(1);
+                self.top = self.top.saturating_sub(1);
+            }
+            self.dropped += 1;
+        }
+
+        let number = self.dropped + self.messages.len();
+        if self.is_shown(&message) {
+            self.shown.push_back(number);
+        }
+        self.messages.push_back(message);
+
+        if self.follow {
+            self.select_last();
+        }
+    }
+
+    /// Record the end of the input, with the result of reading it.
+    pub fn finish(&mut self, result: io::Result<Option<ExitStatus>>) {
+        self.end_of_input = true;
+        match result {
+            Ok(Some(status)) if !status.success() => {
+                self.status = Some(format!("Command failed: {}", status));
+                self.exit_status = Some(status);
+            }
+            Ok(status) => self.exit_status = status,
+            Err(err) => self.status = Some(format!("Error reading input: {}", err)),
+        }
+    }
+
+    fn is_shown(&self, message: &Message) -> bool {
+        !self.hidden.contains(&message.severity) && self.filter.matches(message)
+    }
+
+    /// Apply changed filters, keeping the selection on the same message or the
+    /// next one that is still shown.
+    fn refilter(&mut self) {
+        let selected = self.shown.get(self.selected).copied().unwrap_or(0);
+
+        self.shown = (self.dropped..self.dropped + self.messages.len())
+            .filter(|&number| self.is_shown(self.message(number)))
+            .collect();
+
+        if self.follow {
+            self.select_last();
+        } else {
+            let position = self.shown.partition_point(|&number| number < selected);
+            self.select(position);
+        }
+    }
+
+    pub fn select(&mut self, position: usize) {
+        let position = position.min(self.shown.len().saturating_sub(1));
+        if position != self.selected {
+            self.detail_offset = 0;
+        }
+        self.selected = position;
+    }
+
+    fn select_last(&mut self) {
+        self.select(self.shown.len().saturating_sub(1));
+    }
+
+    fn matches_search(&self, message: &Message) -> bool {
+        let regex = match &self.search {
+            Some(regex) => regex,
+            None => return false,
+        };
+
+        regex.is_match(&message.text)
+            || message.context.values().any(|value| match value {
+                Value::String(value) => regex.is_match(value),
+                value => regex.is_match(&value.to_string()),
+            })
+    }
+
+    /// Select the next (or previous) message matching the search, starting at
+    /// the message at `from`.
+    fn find(&mut self, from: usize, forward: bool) {
+        let found = if forward {
+            (from..self.shown.len()).find(|&position| self.search_matches_at(position))
+        } else {
+            (0..=from.min(self.shown.len()))
+                .rev()
+                .find(|&position| self.search_matches_at(position))
+        };
+
+        match found {
+            Some(position) => {
+                self.follow = false;
+                self.select(position);
+            }
+            None if self.search.is_some() => self.status = Some("No more matches".to_string()),
+            None => {}
+        }
+    }
+
+    fn search_matches_at(&self, position: usize) -> bool {
+        self.shown
+            .get(position)
+            .is_some_and(|&number| self.matches_search(self.message(number)))
+    }
+
+    /// Handle a key press, where `page` is the number of messages on screen,
+    /// returning `false` to quit.
+    pub fn handle_key(&mut self, key: KeyEvent, page: usize) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        if let Some((prompt, previous, origin)) = self.prompt.clone() {
+            match key.code {
+                KeyCode::Enter => self.prompt = None,
+                KeyCode::Esc => {
+                    self.prompt = None;
+                    self.set_prompt_value(prompt, previous, origin);
+                    self.status = None;
+                }
+                KeyCode::Backspace => {
+                    let mut value = self.prompt_value(prompt).to_string();
+                    value.pop();
+                    self.set_prompt_value(prompt, value, origin);
+                }
+                KeyCode::Char(ch) => {
+                    let mut value = self.prompt_value(prompt).to_string();
+                    value.push(ch);
+                    self.set_prompt_value(prompt, value, origin);
+                }
+                _ => {}
+            }
+            return true;
+        }
+
+        self.status = None;
+        let page = page.max(1);
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_back(1),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.select(self.selected + page),
+            KeyCode::PageUp | KeyCode::Char('b') => self.scroll_back(page),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll_back(self.selected),
+            KeyCode::End | KeyCode::Char('G') => self.select_last(),
+
+            KeyCode::Enter | KeyCode::Char('d') => self.detail = !self.detail,
+            KeyCode::Char('J') => self.detail_offset += 1,
+            KeyCode::Char('K') => self.detail_offset = self.detail_offset.saturating_sub(1),
+            KeyCode::Esc => self.detail = false,
+
+            KeyCode::Char('F') => {
+                self.follow = !self.follow;
+                if self.follow {
+                    self.select_last();
+                }
+            }
+
+            KeyCode::Char('/') => {
+                self.prompt = Some((Prompt::Search, self.search_expr.clone(), self.selected));
+            }
+            KeyCode::Char('n') => self.find(self.selected + 1, true),
+            KeyCode::Char('N') if self.selected > 0 => self.find(self.selected - 1, false),
+
+            KeyCode::Char('w') => {
+                self.prompt = Some((Prompt::Filter, self.filter_expr.clone(), self.selected));
+            }
+
+            KeyCode::Char(ch @ '0'..='8') => {
+                let severity = SEVERITIES[ch as usize - '0' as usize];
+                match self.hidden.iter().position(|&hidden| hidden == severity) {
+                    Some(position) => {
+                        self.hidden.remove(position);
+                    }
+                    None => self.hidden.push(severity),
+                }
+                self.refilter();
+            }
+
+            _ => {}
+        }
+
+        true
+    }
+
+    fn scroll_back(&mut self, count: usize) {
+        self.follow = false;
+        self.select(self.selected.saturating_sub(count));
+    }
+
+    fn prompt_value(&self, prompt: Prompt) -> &str {
+        match prompt {
+            Prompt::Search => &self.search_expr,
+            Prompt::Filter => &self.filter_expr,
+        }
+    }
+
+    /// Update a search or filter expression as it is edited, keeping the
+    /// previous search or filter while the expression is invalid.
+    fn set_prompt_value(&mut self, prompt: Prompt, value: String, origin: usize) {
+        self.status = None;
+
+        match prompt {
+            Prompt::Search => {
+                // searches are case-insensitive unless they contain capitals
+                let pattern = if value.chars().any(char::is_uppercase) {
+                    value.clone()
+                } else {
+                    format!("(?i){}", value)
+                };
+
+                match Regex::new(&pattern) {
+                    Ok(_) if value.is_empty() => self.search = None,
+                    Ok(regex) => {
+                        self.search = Some(regex);
+                        self.find(origin, true);
+                    }
+                    Err(_) => self.status = Some("Invalid pattern".to_string()),
+                }
+                self.search_expr = value;
+            }
+            Prompt::Filter => {
+                let filter = if value.trim().is_empty() {
+                    Ok(Filter::All(Vec::new()))
+                } else {
+                    Filter::parse(&value)
+                };
+
+                match filter {
+                    Ok(filter) => {
+                        self.filter = filter;
+                        self.refilter();
+                    }
+                    Err(err) => self.status = Some(err),
+                }
+                self.filter_expr = value;
+            }
+        }
+    }
+
+    /// The text of the prompt being edited, if any.
+    pub fn prompt_line(&self) -> Option<String> {
+        let (prompt, _, _) = self.prompt.as_ref()?;
+        let label = match prompt {
+            Prompt::Search => "/",
+            Prompt::Filter => "where: ",
+        };
+
+        let mut line = format!("{}{}", label, self.prompt_value(*prompt));
+        if let Some(status) = &self.status {
+            line = format!("{}  ({})", line, status);
+        }
+        Some(line)
+    }
+
+    /// The text of the status bar.
+    pub fn status_line(&self) -> String {
+        let mut parts = vec![format!(
+            "{}/{} of {}",
+            (self.selected + 1).min(self.shown.len()),
+            self.shown.len(),
+            self.messages.len()
+        )];
+        if self.dropped > 0 {
+            parts.push(format!("({} dropped)", self.dropped));
+        }
+        if self.follow {
+            parts.push("[follow]".to_string());
+        }
+        if self.end_of_input {
+            parts.push("[end]".to_string());
+        }
+        if !self.hidden.is_empty() {
+            let mut hidden = self.hidden.clone();
+            hidden.sort();
+            let names: Vec<_> = hidden
+                .iter()
+                .map(|severity| severity.to_string().to_lowercase())
+                .collect();
+            parts.push(format!("hidden: {}", names.join(",")));
+        }
+        if !self.filter_expr.is_empty() {
+            parts.push(format!("where: {}", self.filter_expr));
+        }
+        if !self.search_expr.is_empty() {
+            parts.push(format!("/{}", self.search_expr));
+        }
+        parts.push(self.status.clone().unwrap_or_else(|| HELP.to_string()));
+
+        parts.join("  ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn press(state: &mut PagerState, keys: &str) {
+        for ch in keys.chars() {
+            let code = match ch {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                '\x08' => KeyCode::Backspace,
+                ch => KeyCode::Char(ch),
+            };
+            assert!(state.handle_key(KeyEvent::new(code, KeyModifiers::NONE), 10));
+        }
+    }
+
+    fn state(messages: &[(&str, Severity)]) -> PagerState {
+        let mut state = PagerState::new(100);
+        for &(text, severity) in messages {
+            let mut message = Message::from_text(text, severity);
+            message.add_context("id", text.len());
+            state.push(message);
+        }
+        state
+    }
+
+    fn selected(state: &PagerState) -> &str {
+        &state.selected_message().unwrap().text
+    }
+
+    #[test]
+    fn follow_and_select() {
+        let mut state = state(&[("one", Severity::Info), ("two", Severity::Info)]);
+        assert_eq!(selected(&state), "two");
+
+        press(&mut state, "k");
+        assert!(!state.follow);
+        state.push(Message::from_text("three", Severity::Info));
+        assert_eq!(selected(&state), "one");
+
+        press(&mut state, "F");
+        assert_eq!(selected(&state), "three");
+        assert!(state.status_line().starts_with("3/3 of 3  [follow]"));
+    }
+
+    #[test]
+    fn hide_severities() {
+        let mut state = state(&[
+            ("debug", Severity::Debug),
+            ("info", Severity::Info),
+            ("error", Severity::Error),
+        ]);
+        press(&mut state, "2");
+        assert_eq!(state.shown, [0, 2]);
+        assert_eq!(selected(&state), "error");
+        assert!(state.status_line().contains("hidden: info"));
+
+        press(&mut state, "2");
+        assert_eq!(state.shown, [0, 1, 2]);
+        assert_eq!(selected(&state), "error");
+    }
+
+    #[test]
+    fn edit_filter() {
+        let mut state = state(&[
+            ("a", Severity::Info),
+            ("bb", Severity::Info),
+            ("ccc", Severity::Info),
+        ]);
+        press(&mut state, "gjw");
+        assert_eq!(state.prompt_line().unwrap(), "where: ");
+
+        press(&mut state, "id>=2");
+        assert_eq!(state.shown, [1, 2]);
+        assert_eq!(selected(&state), "bb");
+
+        // an invalid expression keeps the previous filter
+        press(&mut state, " and");
+        assert_eq!(state.shown, [1, 2]);
+        assert!(state.prompt_line().unwrap().contains('('));
+
+        press(&mut state, "\x08\x08\x08\x08\n");
+        assert_eq!(state.filter_expr, "id>=2");
+        assert!(state.prompt_line().is_none());
+
+        // escape restores the filter from before the prompt was opened
+        press(&mut state, "w\x08\x08\x08\x08\x08");
+        assert_eq!(state.shown, [0, 1, 2]);
+        press(&mut state, "\x1b");
+        assert_eq!(state.filter_expr, "id>=2");
+        assert_eq!(state.shown, [1, 2]);
+    }
+
+    #[test]
+    fn search() {
+        let mut state = state(&[
+            ("Connected", Severity::Info),
+            ("request", Severity::Info),
+            ("connection lost", Severity::Error),
+        ]);
+        press(&mut state, "g/conn");
+        assert_eq!(selected(&state), "Connected");
+
+        press(&mut state, "\nn");
+        assert_eq!(selected(&state), "connection lost");
+        press(&mut state, "n");
+        assert_eq!(state.status.as_deref(), Some("No more matches"));
+        press(&mut state, "N");
+        assert_eq!(selected(&state), "Connected");
+
+        // capitals make the search case-sensitive
+        press(&mut state, "/\x08\x08\x08\x08Conn\n");
+        press(&mut state, "n");
+        assert_eq!(selected(&state), "Connected");
+        assert_eq!(state.status.as_deref(), Some("No more matches"));
+    }
+
+    #[test]
+    fn drop_oldest_messages() {
+        let mut state = PagerState::new(2);
+        for text in ["one", "two", "three"] {
+            state.push(Message::from_text(text, Severity::Info));
+        }
+        assert_eq!(state.first_number(), 1);
+        assert_eq!(state.shown, [1, 2]);
+        assert_eq!(state.message(1).text, "two");
+        assert!(state.status_line().starts_with("2/2 of 2  (1 dropped)"));
+
+        press(&mut state, "g");
+        state.push(Message::from_text("four", Severity::Info));
+        assert_eq!(state.shown, [2, 3]);
+        assert_eq!(selected(&state), "three");
+    }
+}
@@ -122,6 +122,10 @@ where
     pub fn items(&self) -> impl Iterator<Item = &T> {
         self.items.iter().map(|(item, _)| item)
     }
+
+    pub fn into_items(self) -> impl Iterator<Item = T> {
+        self.items.into_iter().map(|(item, _)| item)
+    }
 }
 
 impl<'a, T, P> Parse<'a> for Punctuated<'a, T, P>
@@ -148,3 +152,121 @@ where
         })
     }
 }
+
+/// A double-quoted string with JSON-style escape sequences.
+///
+/// Unknown escape sequences are kept verbatim.
+#[derive(Debug)]
+pub struct QuotedString(pub String);
+
+impl<'a> Token<'a> for QuotedString {
+    fn parse_token(s: &'a str) -> Result<(QuotedString, &'a str)> {
+        let mut iter = s.chars();
+
+        if iter.next() != Some('"') {
+            return Err(ParseError::custom("Invalid string literal"));
+        }
+
+        let mut value = String::new();
+        while let Some(ch) = iter.next() {
+            match ch {
+                '"' => return Ok((QuotedString(value), iter.as_str())),
+                '\\' => match iter.next() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('/') => value.push('/'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('u') => {
+                        let rest = iter.as_str();
+                        match parse_unicode_escape(rest) {
+                            Some((ch, len)) => {
+                                value.push(ch);
+                                iter = rest[len..].chars();
+                            }
+                            None => value.push_str("\\u"),
+                        }
+                    }
+                    Some(ch) => {
+                        value.push('\\');
+                        value.push(ch);
+                    }
+                    None => break,
+                },
+                _ => value.push(ch),
+            }
+        }
+
+        Err(ParseError::custom("Unterminated string literal"))
+    }
+}
+
+/// Decode the hex digits following a `\u` escape, including UTF-16 surrogate
+/// pairs written as two consecutive escapes.
+///
+/// Returns the decoded character and the number of bytes consumed.
+fn parse_unicode_escape(s: &str) -> Option<(char, usize)> {
+    fn hex4(s: &str) -> Option<u32> {
+        let digits = s.get(..4)?;
+        if digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            u32::from_str_radix(digits, 16).ok()
+        } else {
+            None
+        }
+    }
+
+    let high = hex4(s)?;
+    if (0xd800..0xdc00).contains(&high) {
+        let low = s
+            .get(4..6)
+            .filter(|&p| p == "\\u")
+            .and_then(|_| hex4(&s[6..]))?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+
+        let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+        std::char::from_u32(code).map(|ch| (ch, 10))
+    } else {
+        std::char::from_u32(high).map(|ch| (ch, 4))
+    }
+}
+
+macro_rules! regex_token {
+    ($vis:vis struct $name:ident = $pattern:literal) => {
+        #[derive(Debug)]
+        $vis struct $name<'a>(&'a str);
+
+        impl<'a> AsRef<str> for $name<'a> {
+            fn as_ref(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl<'a> $crate::parse::Token<'a> for $name<'a> {
+            fn parse_token(s: &'a str) -> $crate::parse::Result<(Self, &'a str)> {
+                use lazy_static::lazy_static;
+                use regex::Regex;
+
+                lazy_static!{
+                    static ref RE: Regex = Regex::new($pattern).unwrap();
+                }
+
+                RE.find(s)
+                    .and_then(|m| {
+                        if m.start() == 0 && m.end() > 0 {
+                            Some((Self(m.as_str()), &s[m.end()..]))
+                        } else {
+                            None
+                        }
+                    })
+                    .ok_or_else(|| $crate::parse::ParseError::custom(format!("Could not match token {}", stringify!($name))))
+            }
+        }
+    }
+}
+
+pub(crate) use regex_token;
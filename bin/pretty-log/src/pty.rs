@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::ptr;
+
+/// Open a pseudo-terminal for the output of a command, so that the command
+/// behaves as it would when writing to a terminal, such as by using colours
+/// and line buffering.
+///
+/// Returns a reader for the output and the terminal to give to the command.
+/// The terminal has the size of standard output if it is a terminal, and does
+/// not translate newlines.
+pub fn open() -> io::Result<(PtyReader, File)> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        size.ws_col = 80;
+        size.ws_row = 24;
+    }
+
+    let (mut master, mut slave) = (0, 0);
+    let result =
+        unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &size) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+    unsafe {
+        let mut termios: libc::termios = mem::zeroed();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        termios.c_oflag &= !libc::OPOST;
+        if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((PtyReader(master), slave))
+}
+
+/// Reads the output written to a pseudo-terminal.
+///
+/// Once every process has closed the terminal, reads fail with `EIO` on
+/// Linux, which is taken to be the end of the output.
+pub struct PtyReader(File);
+
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(err) if err.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn read_output() {
+        let (mut reader, mut terminal) = open().unwrap();
+        assert_eq!(unsafe { libc::isatty(terminal.as_raw_fd()) }, 1);
+
+        terminal.write_all(b"one\ntwo\n").unwrap();
+        drop(terminal);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "one\ntwo\n");
+    }
+}
@@ -0,0 +1,110 @@
+use libc::{c_int, c_void, siginfo_t};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Signals that are forwarded to a child process.
+const SIGNALS: [c_int; 4] = [libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM];
+
+/// The process that signals are forwarded to, or 0 if there is none.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+/// Forwards termination signals to a child process, rather than terminating
+/// this process, until it is dropped. The child is then expected to exit,
+/// after which the rest of its output can still be printed.
+pub struct ForwardSignals {
+    /// The actions that were replaced, to restore when dropped.
+    previous: Vec<(c_int, libc::sigaction)>,
+}
+
+impl ForwardSignals {
+    pub fn to(pid: u32) -> io::Result<ForwardSignals> {
+        CHILD.store(pid as i32, Ordering::SeqCst);
+
+        // any actions installed before a failure are restored when this is dropped
+        let mut forward_signals = ForwardSignals {
+            previous: Vec::new(),
+        };
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = forward as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            for &signal in &SIGNALS {
+                let mut previous: libc::sigaction = mem::zeroed();
+                if libc::sigaction(signal, &action, &mut previous) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                forward_signals.previous.push((signal, previous));
+            }
+        }
+
+        Ok(forward_signals)
+    }
+}
+
+impl Drop for ForwardSignals {
+    fn drop(&mut self) {
+        unsafe {
+            for (signal, previous) in &self.previous {
+                libc::sigaction(*signal, previous, ptr::null_mut());
+            }
+        }
+        CHILD.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Terminate the child process that signals are forwarded to, if any.
+pub fn terminate_child() {
+    let child = CHILD.load(Ordering::SeqCst);
+    if child > 0 {
+        unsafe {
+            libc::kill(child, libc::SIGTERM);
+        }
+    }
+}
+
+extern "C" fn forward(signal: c_int, info: *mut siginfo_t, _: *mut c_void) {
+    let child = CHILD.load(Ordering::SeqCst);
+
+    // signals from the terminal (which have no sending process) are sent to
+    // the whole process group, so the child already has them
+    let sender = unsafe { (*info).si_pid() };
+    if child > 0 && sender != 0 && sender != child {
+        unsafe {
+            libc::kill(child, signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn handler(signal: c_int) -> libc::sighandler_t {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            libc::sigaction(signal, ptr::null(), &mut action);
+            action.sa_sigaction
+        }
+    }
+
+    #[test]
+    fn restore_handlers() {
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_IGN);
+        }
+
+        let forward_signals = ForwardSignals::to(u32::MAX >> 1).unwrap();
+        assert_eq!(
+            handler(libc::SIGHUP),
+            forward as *const () as libc::sighandler_t
+        );
+        drop(forward_signals);
+
+        assert_eq!(handler(libc::SIGHUP), libc::SIG_IGN);
+        assert_eq!(CHILD.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,148 @@
+use flate2::read::MultiGzDecoder;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often to check a followed file for new data, truncation or rotation.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Open a log file for reading.
+///
+/// Gzip-compressed files (with a `.gz` extension) are decompressed, and are
+/// never followed since rotated files do not change. A path of `-` reads from
+/// standard input.
+pub fn open(path: &Path, follow: bool) -> io::Result<Box<dyn Read + Send>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdin()));
+    }
+
+    let with_path =
+        |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", path.display(), err));
+
+    if path.extension() == Some(OsStr::new("gz")) {
+        let file = File::open(path).map_err(with_path)?;
+        Ok(Box::new(MultiGzDecoder::new(file)))
+    } else if follow {
+        Ok(Box::new(FollowReader::new(path)))
+    } else {
+        Ok(Box::new(File::open(path).map_err(with_path)?))
+    }
+}
+
+/// A reader that keeps reading from a file as it grows, like `tail -F`.
+///
+/// The file is reopened when it is replaced (such as by rename-based log
+/// rotation) and read from the start again when it is truncated. A missing
+/// file is waited for. Reads never return end-of-file.
+pub struct FollowReader {
+    path: PathBuf,
+    file: Option<File>,
+    inode: u64,
+    position: u64,
+}
+
+impl FollowReader {
+    pub fn new(path: impl Into<PathBuf>) -> FollowReader {
+        FollowReader {
+            path: path.into(),
+            file: None,
+            inode: 0,
+            position: 0,
+        }
+    }
+
+    /// Check whether the file was rotated or truncated since it was opened,
+    /// and reopen or rewind it if so.
+    ///
+    /// Returns `true` if there may be new data to read.
+    fn reopen_if_changed(&mut self) -> io::Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(false),
+        };
+
+        match &mut self.file {
+            Some(file) if metadata.ino() != self.inode => {
+                // finish reading what was written before the file was rotated
+                if file.metadata()?.len() > self.position {
+                    return Ok(true);
+                }
+
+                self.file = None;
+                self.reopen_if_changed()
+            }
+            Some(file) if metadata.len() < self.position => {
+                file.seek(SeekFrom::Start(0))?;
+                self.position = 0;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => match File::open(&self.path) {
+                Ok(file) => {
+                    self.inode = file.metadata()?.ino();
+                    self.file = Some(file);
+                    self.position = 0;
+                    Ok(true)
+                }
+                Err(_) => Ok(false),
+            },
+        }
+    }
+}
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.file {
+                let len = file.read(buf)?;
+                if len > 0 {
+                    self.position += len as u64;
+                    return Ok(len);
+                }
+            }
+
+            if !self.reopen_if_changed()? {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn read(reader: &mut FollowReader, len: usize) -> String {
+        let mut buf = vec![0; len];
+        reader.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn follow_rotation() {
+        let dir = std::env::temp_dir().join(format!("pretty-log-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "one\n").unwrap();
+
+        let mut reader = FollowReader::new(&path);
+        assert_eq!(read(&mut reader, 4), "one\n");
+
+        // a line is written just before the file is rotated by renaming it,
+        // and the rotation is noticed before the line is read
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"two\n").unwrap();
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "three\n").unwrap();
+        assert!(reader.reopen_if_changed().unwrap());
+
+        assert_eq!(read(&mut reader, 6), "two\nth");
+        assert_eq!(read(&mut reader, 4), "ree\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+
+use crate::utils::parse_duration;
+
+/// A range of time that messages must fall within to be shown.
+#[derive(Clone, Debug, Default)]
+pub struct TimeRange {
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: DateTime<Local>) -> bool {
+        self.since.is_none_or(|since| time >= since) && !self.is_past(time)
+    }
+
+    /// Check whether `time` is after the end of the range.
+    pub fn is_past(&self, time: DateTime<Local>) -> bool {
+        self.until.is_some_and(|until| time > until)
+    }
+}
+
+/// Parse an absolute or relative point in time.
+///
+/// Accepts RFC 3339 timestamps, local dates and times (such as `2020-03-01`,
+/// `2020-03-01 14:05` or `14:05` for today), `now`, `today`, `yesterday` and
+/// relative times (such as `15m ago` or `-2h`).
+pub fn parse_time(s: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid time: {}", s);
+
+    match s {
+        "now" => return Ok(now),
+        "today" => return local(now.date_naive().and_time(NaiveTime::MIN)).ok_or_else(invalid),
+        "yesterday" => {
+            let date = now.date_naive() - Duration::days(1);
+            return local(date.and_time(NaiveTime::MIN)).ok_or_else(invalid);
+        }
+        _ => (),
+    }
+
+    let relative = s
+        .strip_suffix("ago")
+        .or_else(|| s.strip_prefix('-'))
+        .map(str::trim);
+    if let Some(relative) = relative {
+        let duration = parse_duration(relative)?;
+        let duration = Duration::from_std(duration).map_err(|_| invalid())?;
+        return now.checked_sub_signed(duration).ok_or_else(invalid);
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.into());
+    }
+
+    const DATETIME_FORMATS: &[&str] = &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ];
+    for format in DATETIME_FORMATS {
+        if let Ok(time) = NaiveDateTime::parse_from_str(s, format) {
+            return local(time).ok_or_else(invalid);
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return local(date.and_time(NaiveTime::MIN)).ok_or_else(invalid);
+    }
+
+    for format in &["%H:%M:%S%.f", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+            return local(now.date_naive().and_time(time)).ok_or_else(invalid);
+        }
+    }
+
+    Err(invalid())
+}
+
+fn local(time: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&time).earliest()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2020, 3, 1, 15, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn absolute_times() {
+        assert_eq!(
+            parse_time("2020-03-01T12:00:00Z", now()).unwrap(),
+            DateTime::parse_from_rfc3339("2020-03-01T12:00:00Z").unwrap()
+        );
+        assert_eq!(
+            parse_time("2020-02-28 09:15", now()).unwrap(),
+            Local.with_ymd_and_hms(2020, 2, 28, 9, 15, 0).unwrap()
+        );
+        assert_eq!(
+            parse_time("2020-02-28", now()).unwrap(),
+            Local.with_ymd_and_hms(2020, 2, 28, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_time("14:05", now()).unwrap(),
+            Local.with_ymd_and_hms(2020, 3, 1, 14, 5, 0).unwrap()
+        );
+        assert!(parse_time("soon", now()).is_err());
+    }
+
+    #[test]
+    fn relative_times() {
+        assert_eq!(parse_time("now", now()).unwrap(), now());
+        assert_eq!(
+            parse_time("15m ago", now()).unwrap(),
+            Local.with_ymd_and_hms(2020, 3, 1, 15, 15, 0).unwrap()
+        );
+        assert_eq!(
+            parse_time("2 hours ago", now()).unwrap(),
+            Local.with_ymd_and_hms(2020, 3, 1, 13, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_time("-1d", now()).unwrap(),
+            Local.with_ymd_and_hms(2020, 2, 29, 15, 30, 0).unwrap()
+        );
+        assert!(parse_time("1e300d ago", now()).is_err());
+        assert!(parse_time("99999999999999d ago", now()).is_err());
+        assert!(parse_time("9999999999999999999999d ago", now()).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use serde::Deserialize;
 use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
@@ -31,3 +32,48 @@ pub fn is_stdout_tty() -> bool {
         result.unwrap() > 0
     }
 }
+
+/// Get the width of the terminal, from `$COLUMNS` or else from standard output
+/// if it is a terminal.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+    {
+        return Some(columns);
+    }
+
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(1, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            Some(size.ws_col as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse a duration such as `500ms`, `15s`, `2m`, `1h` or `1d`.
+///
+/// A number without a unit is taken to be seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+        .unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", s))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" | "sec" | "secs" | "second" | "seconds" => value,
+        "m" | "min" | "mins" | "minute" | "minutes" => value * 60.0,
+        "h" | "hour" | "hours" => value * 3600.0,
+        "d" | "day" | "days" => value * 86400.0,
+        unit => return Err(format!("Unknown duration unit: {}", unit)),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("Duration out of range: {}", s))
+}
@@ -0,0 +1,187 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+fn pretty_log(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pretty-log"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .env("HOME", env::temp_dir())
+        .env("XDG_CONFIG_HOME", env::temp_dir())
+        .current_dir(env::temp_dir())
+        .output()
+        .expect("run pretty-log")
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("pretty-log-{}-{}", process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn unsorted_file_until() {
+    let path = temp_file(
+        "unsorted.log",
+        concat!(
+            r#"{"time":"2020-03-01T00:03:00Z","msg":"three"}"#,
+            "\n",
+            r#"{"time":"2020-03-01T00:01:00Z","msg":"one"}"#,
+            "\n",
+            r#"{"time":"2020-03-01T00:02:00Z","msg":"two"}"#,
+            "\n",
+        ),
+    );
+    let file = path.to_str().unwrap();
+    let until = ["--until", "2020-03-01T00:02:00Z", "-o", "json", "-f", file];
+
+    let output = pretty_log(&until);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert_eq!(stdout.lines().count(), 2);
+    assert!(stdout.contains("one") && stdout.contains("two"));
+
+    let output = pretty_log(&[&until[..], &["--sorted"]].concat());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn group_container_payloads() {
+    let path = temp_file(
+        "docker.log",
+        concat!(
+            r#"{"log":"Exception in thread \"main\"\n","stream":"stderr"}"#,
+            "\n",
+            r#"{"log":"\tat Main.main(Main.java:3)\n","stream":"stderr"}"#,
+            "\n",
+            r#"{"log":"done\n","stream":"stdout"}"#,
+            "\n",
+        ),
+    );
+    let file = path.to_str().unwrap();
+
+    let output = pretty_log(&["-i", "docker", "--multiline", "-o", "json", "-f", file]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0]
+        .contains(r#""message":"Exception in thread \"main\"\n\tat Main.main(Main.java:3)""#));
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn command_exit_status() {
+    let output = pretty_log(&["sh", "-c", "echo failed >&2; exit 3"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("failed"));
+
+    let output = pretty_log(&["sh", "-c", "kill -TERM $$"]);
+    assert_eq!(output.status.code(), Some(143));
+
+    let output = pretty_log(&["true"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn pty_output() {
+    let command = ["sh", "-c", "echo out; echo err >&2"];
+    let output = pretty_log(&[&["--pty", "-o", "json"][..], &command].concat());
+    assert!(output.status.success());
+
+    // the two streams are read separately, so the order of the lines is not fixed
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut messages: Vec<_> = stdout
+        .lines()
+        .map(|line| {
+            let message: serde_json::Value = serde_json::from_str(line).unwrap();
+            (message["severity"].clone(), message["message"].clone())
+        })
+        .collect();
+    messages.sort_by_key(|message| message.1.to_string());
+    assert_eq!(
+        messages,
+        [
+            ("error".into(), "err".into()),
+            ("info".into(), "out".into())
+        ],
+        "{}",
+        stdout
+    );
+
+    let output = pretty_log(&["--pty", "-o", "json"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--pty needs a command"));
+}
+
+#[test]
+fn unicode_escapes() {
+    let path = temp_file(
+        "escapes.log",
+        concat!(
+            r#"time="2020-03-01T12:00:00Z" level=info msg="caf\u00e9 opened""#,
+            "\n",
+            r#"at=info msg="na\u00efve""#,
+            "\n",
+        ),
+    );
+    let file = path.to_str().unwrap();
+
+    let output = pretty_log(&["-o", "json", "-f", file]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""message":"café opened""#), "{}", stdout);
+    assert!(stdout.contains(r#""message":"naïve""#), "{}", stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn interleaved_partial_lines() {
+    let path = temp_file(
+        "partial.log",
+        concat!(
+            r#"{"log":"out ","stream":"stdout"}"#,
+            "\n",
+            r#"{"log":"err ","stream":"stderr"}"#,
+            "\n",
+            r#"{"log":"one\n","stream":"stdout"}"#,
+            "\n",
+            r#"{"log":"two\n","stream":"stderr"}"#,
+            "\n",
+        ),
+    );
+    let file = path.to_str().unwrap();
+
+    let output = pretty_log(&["-i", "docker", "-o", "json", "-f", file]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].contains(r#""severity":"info","message":"out one""#));
+    assert!(lines[1].contains(r#""severity":"error","message":"err two""#));
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_context() {
+    let path = temp_file(
+        "caller.log",
+        "time=\"2020-03-01T12:00:00Z\" level=info msg=started file=\"main.go:12\"\n",
+    );
+    let file = path.to_str().unwrap();
+
+    let output = pretty_log(&["-o", "json", "-f", file]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""file":"main.go:12""#), "{}", stdout);
+    assert!(
+        stdout.contains(&format!(r#""_file":"{}""#, file)),
+        "{}",
+        stdout
+    );
+
+    fs::remove_file(path).unwrap();
+}